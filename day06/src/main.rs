@@ -1,59 +1,469 @@
 use std::collections::HashMap;
+use std::env;
+use std::fmt;
 use std::fs;
+use std::thread;
 
 type Occurrences = HashMap<char, usize>;
 
+#[derive(Copy, Clone)]
+enum Rank {
+    MostCommon(usize),
+    LeastCommon(usize),
+}
+
+struct Tie {
+    position: usize,
+    candidates: Vec<char>,
+    chosen: char,
+}
+
+struct Decoded {
+    message: String,
+    ties: Vec<Tie>,
+}
+
 fn part_1(indexed_occurrences: &[Occurrences]) -> String {
-    indexed_occurrences
-        .iter()
-        .map(|letters| {
-            letters
-                .iter()
-                .max_by(|lhs, rhs| lhs.1.cmp(rhs.1))
-                .unwrap()
-                .0
-        })
-        .collect::<String>()
+    decode(indexed_occurrences, Rank::MostCommon(0))
+        .unwrap()
+        .message
 }
 
 fn part_2(indexed_occurrences: &[Occurrences]) -> String {
-    indexed_occurrences
+    decode(indexed_occurrences, Rank::LeastCommon(0))
+        .unwrap()
+        .message
+}
+
+// letters with equal counts are always ordered alphabetically, for both ranks
+fn histogram(letters: &Occurrences, rank: Rank) -> Vec<(char, usize)> {
+    let mut histogram: Vec<(char, usize)> = letters.iter().map(|(c, n)| (*c, *n)).collect();
+    match rank {
+        Rank::MostCommon(_) => {
+            histogram.sort_by(|lhs, rhs| rhs.1.cmp(&lhs.1).then(lhs.0.cmp(&rhs.0)))
+        }
+        Rank::LeastCommon(_) => {
+            histogram.sort_by(|lhs, rhs| lhs.1.cmp(&rhs.1).then(lhs.0.cmp(&rhs.0)))
+        }
+    }
+
+    histogram
+}
+
+fn decode(indexed_occurrences: &[Occurrences], rank: Rank) -> Option<Decoded> {
+    let k = match rank {
+        Rank::MostCommon(k) | Rank::LeastCommon(k) => k,
+    };
+
+    let mut message = String::new();
+    let mut ties = Vec::new();
+    for (position, letters) in indexed_occurrences.iter().enumerate() {
+        let histogram = histogram(letters, rank);
+        let (chosen, count) = *histogram.get(k)?;
+
+        let candidates: Vec<char> = histogram
+            .iter()
+            .filter(|(_, n)| *n == count)
+            .map(|(c, _)| *c)
+            .collect();
+        if candidates.len() > 1 {
+            ties.push(Tie {
+                position,
+                candidates,
+                chosen,
+            });
+        }
+
+        message.push(chosen);
+    }
+
+    Some(Decoded { message, ties })
+}
+
+enum NoiseModel {
+    Uniform {
+        alphabet: Vec<char>,
+        accuracy: f64,
+    },
+    // matrix[i][j] is the probability of receiving alphabet[j] when alphabet[i] was sent
+    Confusion {
+        alphabet: Vec<char>,
+        matrix: Vec<Vec<f64>>,
+    },
+}
+
+#[derive(Debug, PartialEq)]
+enum ModelError {
+    EmptyAlphabet,
+    AccuracyOutOfRange,
+    MatrixSize { rows: usize },
+    RowSize { row: usize },
+    RowSum { row: usize },
+}
+
+impl fmt::Display for ModelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ModelError::EmptyAlphabet => write!(f, "the alphabet is empty"),
+            ModelError::AccuracyOutOfRange => write!(f, "accuracy is not between 0 and 1"),
+            ModelError::MatrixSize { rows } => {
+                write!(f, "the matrix has {} rows instead of one per letter", rows)
+            }
+            ModelError::RowSize { row } => {
+                write!(f, "row {} does not have one column per letter", row)
+            }
+            ModelError::RowSum { row } => {
+                write!(f, "row {} is not a probability distribution", row)
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum DecodeError {
+    UnknownLetter { position: usize, letter: char },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::UnknownLetter { position, letter } => write!(
+                f,
+                "'{}' at position {} is not in the model's alphabet",
+                letter, position
+            ),
+        }
+    }
+}
+
+const ROW_SUM_TOLERANCE: f64 = 1e-6;
+
+impl NoiseModel {
+    fn uniform(alphabet: Vec<char>, accuracy: f64) -> Result<Self, ModelError> {
+        if alphabet.is_empty() {
+            return Err(ModelError::EmptyAlphabet);
+        }
+        if !(0.0..=1.0).contains(&accuracy) {
+            return Err(ModelError::AccuracyOutOfRange);
+        }
+        Ok(NoiseModel::Uniform { alphabet, accuracy })
+    }
+
+    fn confusion(alphabet: Vec<char>, matrix: Vec<Vec<f64>>) -> Result<Self, ModelError> {
+        if alphabet.is_empty() {
+            return Err(ModelError::EmptyAlphabet);
+        }
+        if matrix.len() != alphabet.len() {
+            return Err(ModelError::MatrixSize { rows: matrix.len() });
+        }
+        for (row, probabilities) in matrix.iter().enumerate() {
+            if probabilities.len() != alphabet.len() {
+                return Err(ModelError::RowSize { row });
+            }
+            let sum: f64 = probabilities.iter().sum();
+            if probabilities.iter().any(|p| !(0.0..=1.0).contains(p))
+                || (sum - 1.0).abs() > ROW_SUM_TOLERANCE
+            {
+                return Err(ModelError::RowSum { row });
+            }
+        }
+        Ok(NoiseModel::Confusion { alphabet, matrix })
+    }
+
+    fn alphabet(&self) -> &[char] {
+        match self {
+            NoiseModel::Uniform { alphabet, .. } | NoiseModel::Confusion { alphabet, .. } => {
+                alphabet
+            }
+        }
+    }
+
+    fn probability(&self, sent: usize, received: usize) -> f64 {
+        match self {
+            NoiseModel::Uniform { alphabet, accuracy } => {
+                if sent == received {
+                    *accuracy
+                } else {
+                    (1.0 - accuracy) / (alphabet.len() - 1).max(1) as f64
+                }
+            }
+            NoiseModel::Confusion { matrix, .. } => matrix[sent][received],
+        }
+    }
+}
+
+struct Estimate {
+    letter: char,
+    confidence: f64,
+}
+
+struct LikelihoodDecoded {
+    message: String,
+    estimates: Vec<Estimate>,
+    uncertain: Vec<usize>,
+}
+
+fn estimate(
+    position: usize,
+    letters: &Occurrences,
+    model: &NoiseModel,
+) -> Result<Estimate, DecodeError> {
+    let alphabet = model.alphabet();
+    let received: Vec<(usize, usize)> = letters
         .iter()
-        .map(|letters| {
-            letters
+        .map(|(c, count)| match alphabet.iter().position(|a| a == c) {
+            Some(j) => Ok((j, *count)),
+            None => Err(DecodeError::UnknownLetter {
+                position,
+                letter: *c,
+            }),
+        })
+        .collect::<Result<_, _>>()?;
+
+    let log_likelihoods: Vec<f64> = (0..alphabet.len())
+        .map(|sent| {
+            received
                 .iter()
-                .min_by(|lhs, rhs| lhs.1.cmp(rhs.1))
-                .unwrap()
-                .0
+                .map(|&(j, count)| {
+                    count as f64 * model.probability(sent, j).max(f64::MIN_POSITIVE).ln()
+                })
+                .sum()
         })
-        .collect::<String>()
+        .collect();
+
+    let best = (0..alphabet.len()).fold(0, |best, i| {
+        if log_likelihoods[i] > log_likelihoods[best] {
+            i
+        } else {
+            best
+        }
+    });
+    let normalizer: f64 = log_likelihoods
+        .iter()
+        .map(|l| (l - log_likelihoods[best]).exp())
+        .sum();
+
+    Ok(Estimate {
+        letter: alphabet[best],
+        confidence: 1.0 / normalizer,
+    })
 }
 
-fn count_occurences(input: &str) -> Vec<Occurrences> {
-    let mut indexed_occurrences = Vec::new();
-    for line in input.lines() {
+fn decode_likelihood(
+    indexed_occurrences: &[Occurrences],
+    model: &NoiseModel,
+    threshold: f64,
+) -> Result<LikelihoodDecoded, DecodeError> {
+    let estimates: Vec<Estimate> = indexed_occurrences
+        .iter()
+        .enumerate()
+        .map(|(position, letters)| estimate(position, letters, model))
+        .collect::<Result<_, _>>()?;
+
+    Ok(LikelihoodDecoded {
+        message: estimates.iter().map(|e| e.letter).collect(),
+        uncertain: estimates
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| e.confidence < threshold)
+            .map(|(position, _)| position)
+            .collect(),
+        estimates,
+    })
+}
+
+const ASCII_SIZE: usize = 128;
+
+#[derive(Clone)]
+struct PositionCounter {
+    ascii: [usize; ASCII_SIZE],
+    unicode: HashMap<char, usize>,
+}
+
+impl Default for PositionCounter {
+    fn default() -> Self {
+        PositionCounter {
+            ascii: [0; ASCII_SIZE],
+            unicode: HashMap::new(),
+        }
+    }
+}
+
+impl PositionCounter {
+    fn add(&mut self, c: char, count: usize) {
+        if c.is_ascii() {
+            self.ascii[c as usize] += count;
+        } else {
+            *self.unicode.entry(c).or_insert(0) += count;
+        }
+    }
+
+    fn merge(&mut self, other: &PositionCounter) {
+        self.ascii
+            .iter_mut()
+            .zip(other.ascii.iter())
+            .for_each(|(lhs, rhs)| *lhs += rhs);
+        other
+            .unicode
+            .iter()
+            .for_each(|(c, count)| self.add(*c, *count));
+    }
+
+    fn occurrences(&self) -> Occurrences {
+        let ascii = self
+            .ascii
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(c, count)| (c as u8 as char, *count));
+        let unicode = self.unicode.iter().map(|(c, count)| (*c, *count));
+
+        ascii.chain(unicode).collect()
+    }
+}
+
+#[derive(Clone, Default)]
+struct FrequencyCounter {
+    positions: Vec<PositionCounter>,
+}
+
+impl FrequencyCounter {
+    fn push_line(&mut self, line: &str) {
         for (index, c) in line.chars().enumerate() {
-            if indexed_occurrences.len() <= index {
-                indexed_occurrences.push(Occurrences::new());
+            if self.positions.len() <= index {
+                self.positions.push(PositionCounter::default());
             }
 
-            if let Some(occurence) = indexed_occurrences.get_mut(index) {
-                let counter = (*occurence).entry(c).or_insert(0);
-                *counter += 1;
-            }
+            self.positions[index].add(c, 1);
+        }
+    }
+
+    fn merge(&mut self, other: &FrequencyCounter) {
+        if self.positions.len() < other.positions.len() {
+            self.positions
+                .resize_with(other.positions.len(), PositionCounter::default);
         }
+
+        self.positions
+            .iter_mut()
+            .zip(other.positions.iter())
+            .for_each(|(lhs, rhs)| lhs.merge(rhs));
+    }
+
+    fn occurrences(&self) -> Vec<Occurrences> {
+        self.positions
+            .iter()
+            .map(PositionCounter::occurrences)
+            .collect()
     }
+}
+
+fn count_occurences(input: &str) -> Vec<Occurrences> {
+    let mut counter = FrequencyCounter::default();
+    input.lines().for_each(|line| counter.push_line(line));
 
-    indexed_occurrences
+    counter.occurrences()
+}
+
+fn count_occurences_parallel(input: &str, chunks: usize) -> Vec<Occurrences> {
+    let lines: Vec<&str> = input.lines().collect();
+    let chunk_size = lines.len().div_ceil(chunks.max(1));
+
+    let counters: Vec<FrequencyCounter> = thread::scope(|scope| {
+        let handles: Vec<_> = lines
+            .chunks(chunk_size.max(1))
+            .map(|chunk| {
+                scope.spawn(move || {
+                    let mut counter = FrequencyCounter::default();
+                    chunk.iter().for_each(|line| counter.push_line(line));
+                    counter
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect()
+    });
+
+    let mut counter = FrequencyCounter::default();
+    counters.iter().for_each(|other| counter.merge(other));
+
+    counter.occurrences()
 }
 
 fn main() {
     let input = fs::read_to_string("input").expect("file not found");
     let input = input.trim();
 
-    let indexed_occurrences = count_occurences(input);
+    // `cargo run -- parallel [threads]` counts the letters on that many threads,
+    // `cargo run -- ties` lists the positions where equally common letters were chosen between,
+    // `cargo run -- likelihood [accuracy | matrix file]` decodes by maximum likelihood, with every
+    // letter arriving intact with the given probability or with a confusion matrix read from a
+    // file, one line of 26 probabilities for each sent letter
+    let mut args = env::args().skip(1);
+    let command = args.next();
+    let indexed_occurrences = match command.as_deref() {
+        Some("parallel") => {
+            let threads = args.next().and_then(|n| n.parse().ok()).unwrap_or(4);
+            count_occurences_parallel(input, threads)
+        }
+        _ => count_occurences(input),
+    };
     assert_eq!("umejzgdw", part_1(&indexed_occurrences));
     assert_eq!("aovueakv", part_2(&indexed_occurrences));
+
+    match command.as_deref() {
+        Some("ties") => {
+            for (name, rank) in &[
+                ("most", Rank::MostCommon(0)),
+                ("least", Rank::LeastCommon(0)),
+            ] {
+                let decoded = decode(&indexed_occurrences, *rank).unwrap();
+                for tie in decoded.ties {
+                    let candidates: String = tie.candidates.iter().collect();
+                    println!(
+                        "{} common, position {}: {} from {}",
+                        name, tie.position, tie.chosen, candidates
+                    );
+                }
+            }
+        }
+        Some("likelihood") => {
+            let alphabet: Vec<char> = ('a'..='z').collect();
+            let argument = args.next().unwrap_or_else(|| String::from("0.5"));
+            let model = match argument.parse() {
+                Ok(accuracy) => NoiseModel::uniform(alphabet, accuracy),
+                Err(_) => {
+                    let matrix = fs::read_to_string(&argument).expect("file not found");
+                    let matrix = matrix
+                        .lines()
+                        .map(|line| line.split_whitespace().map(|p| p.parse()).collect())
+                        .collect::<Result<_, _>>()
+                        .expect("matrix entries have to be numbers");
+                    NoiseModel::confusion(alphabet, matrix)
+                }
+            }
+            .unwrap_or_else(|error| panic!("{}", error));
+
+            let decoded = decode_likelihood(&indexed_occurrences, &model, 0.99)
+                .unwrap_or_else(|error| panic!("{}", error));
+            println!("{}", decoded.message);
+            for (position, estimate) in decoded.estimates.iter().enumerate() {
+                let mark = if decoded.uncertain.contains(&position) {
+                    " uncertain"
+                } else {
+                    ""
+                };
+                println!(
+                    "{}: {} {:.4}{}",
+                    position, estimate.letter, estimate.confidence, mark
+                );
+            }
+        }
+        _ => {}
+    }
 }
 
 #[cfg(test)]
@@ -88,4 +498,197 @@ mod tests {
         let indexed_occurrences = count_occurences(INPUT);
         assert_eq!("advent", part_2(&indexed_occurrences));
     }
+
+    #[test]
+    fn test_count_occurences_parallel() {
+        for chunks in 1..=20 {
+            let indexed_occurrences = count_occurences_parallel(INPUT, chunks);
+            assert_eq!("easter", part_1(&indexed_occurrences));
+            assert_eq!("advent", part_2(&indexed_occurrences));
+        }
+    }
+
+    #[test]
+    fn test_count_occurences_parallel_zero_chunks() {
+        let indexed_occurrences = count_occurences_parallel(INPUT, 0);
+        assert_eq!("easter", part_1(&indexed_occurrences));
+        assert!(count_occurences_parallel("", 0).is_empty());
+    }
+
+    #[test]
+    fn test_frequency_counter_merge() {
+        let mut lhs = FrequencyCounter::default();
+        lhs.push_line("ab");
+        lhs.push_line("a");
+        let mut rhs = FrequencyCounter::default();
+        rhs.push_line("bbą");
+        lhs.merge(&rhs);
+
+        let indexed_occurrences = lhs.occurrences();
+        assert_eq!(3, indexed_occurrences.len());
+        assert_eq!(Some(&2), indexed_occurrences[0].get(&'a'));
+        assert_eq!(Some(&1), indexed_occurrences[0].get(&'b'));
+        assert_eq!(Some(&2), indexed_occurrences[1].get(&'b'));
+        assert_eq!(Some(&1), indexed_occurrences[2].get(&'ą'));
+        assert_eq!(1, indexed_occurrences[2].len());
+    }
+
+    #[test]
+    fn test_decode_likelihood_uniform() {
+        let indexed_occurrences = count_occurences(INPUT);
+        let model = NoiseModel::uniform(('a'..='z').collect(), 0.3).unwrap();
+        let decoded = decode_likelihood(&indexed_occurrences, &model, 0.9).unwrap();
+        assert_eq!("easter", decoded.message);
+        assert_eq!(6, decoded.estimates.len());
+        assert!(decoded
+            .estimates
+            .iter()
+            .all(|e| e.confidence > 0.0 && e.confidence <= 1.0));
+
+        // an accuracy below chance level makes the rarest letter the most likely one
+        let model = NoiseModel::uniform("adenrstv".chars().collect(), 0.01).unwrap();
+        let decoded = decode_likelihood(&indexed_occurrences, &model, 0.9).unwrap();
+        assert_eq!("advent", decoded.message);
+    }
+
+    #[test]
+    fn test_decode_likelihood_confusion() {
+        let indexed_occurrences = count_occurences("a\na\nb\nb\nb\n");
+        let uniform = NoiseModel::uniform(vec!['a', 'b'], 0.9).unwrap();
+        let decoded = decode_likelihood(&indexed_occurrences, &uniform, 0.95).unwrap();
+        assert_eq!("b", decoded.message);
+        assert!(decoded.estimates[0].confidence > 0.5);
+        assert_eq!(vec![0], decoded.uncertain);
+
+        // 'a' is often received as 'b', so three 'b's are weak evidence against it
+        let confusion =
+            NoiseModel::confusion(vec!['a', 'b'], vec![vec![0.4, 0.6], vec![0.01, 0.99]]).unwrap();
+        let decoded = decode_likelihood(&indexed_occurrences, &confusion, 0.95).unwrap();
+        assert_eq!("a", decoded.message);
+        assert!(decoded.estimates[0].confidence > 0.99);
+        assert!(decoded.uncertain.is_empty());
+    }
+
+    #[test]
+    fn test_confusion_matches_uniform() {
+        let indexed_occurrences = count_occurences(INPUT);
+        let alphabet: Vec<char> = ('a'..='z').collect();
+        let uniform = NoiseModel::uniform(alphabet.clone(), 0.3).unwrap();
+
+        // the same noise written out as a matrix gives the same letters with the same confidence
+        let matrix = (0..alphabet.len())
+            .map(|i| {
+                (0..alphabet.len())
+                    .map(|j| if i == j { 0.3 } else { 0.7 / 25.0 })
+                    .collect()
+            })
+            .collect();
+        let confusion = NoiseModel::confusion(alphabet, matrix).unwrap();
+
+        let lhs = decode_likelihood(&indexed_occurrences, &uniform, 0.9).unwrap();
+        let rhs = decode_likelihood(&indexed_occurrences, &confusion, 0.9).unwrap();
+        assert_eq!(lhs.message, rhs.message);
+        assert_eq!(lhs.uncertain, rhs.uncertain);
+        for (lhs, rhs) in lhs.estimates.iter().zip(rhs.estimates.iter()) {
+            assert_eq!(lhs.letter, rhs.letter);
+            assert!((lhs.confidence - rhs.confidence).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_noise_model_errors() {
+        assert_eq!(
+            Some(ModelError::EmptyAlphabet),
+            NoiseModel::uniform(vec![], 0.5).err()
+        );
+        assert_eq!(
+            Some(ModelError::AccuracyOutOfRange),
+            NoiseModel::uniform(vec!['a'], 1.5).err()
+        );
+        assert_eq!(
+            Some(ModelError::EmptyAlphabet),
+            NoiseModel::confusion(vec![], vec![]).err()
+        );
+        assert_eq!(
+            Some(ModelError::MatrixSize { rows: 1 }),
+            NoiseModel::confusion(vec!['a', 'b'], vec![vec![0.5, 0.5]]).err()
+        );
+        assert_eq!(
+            Some(ModelError::RowSize { row: 1 }),
+            NoiseModel::confusion(vec!['a', 'b'], vec![vec![0.5, 0.5], vec![1.0]]).err()
+        );
+        assert_eq!(
+            Some(ModelError::RowSum { row: 0 }),
+            NoiseModel::confusion(vec!['a', 'b'], vec![vec![0.5, 0.6], vec![0.5, 0.5]]).err()
+        );
+        assert_eq!(
+            Some(ModelError::RowSum { row: 1 }),
+            NoiseModel::confusion(vec!['a', 'b'], vec![vec![0.5, 0.5], vec![1.5, -0.5]]).err()
+        );
+        assert_eq!(
+            "row 1 is not a probability distribution",
+            ModelError::RowSum { row: 1 }.to_string()
+        );
+    }
+
+    #[test]
+    fn test_decode_likelihood_unknown_letter() {
+        let indexed_occurrences = count_occurences("ab\nac\n");
+        let model = NoiseModel::uniform(vec!['a', 'b'], 0.9).unwrap();
+        let error = decode_likelihood(&indexed_occurrences, &model, 0.9)
+            .err()
+            .unwrap();
+        assert_eq!(
+            DecodeError::UnknownLetter {
+                position: 1,
+                letter: 'c'
+            },
+            error
+        );
+        assert_eq!(
+            "'c' at position 1 is not in the model's alphabet",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn test_histogram() {
+        let indexed_occurrences = count_occurences("ab\nac\nbc\n");
+        assert_eq!(
+            vec![('a', 2), ('b', 1)],
+            histogram(&indexed_occurrences[0], Rank::MostCommon(0))
+        );
+        assert_eq!(
+            vec![('b', 1), ('c', 2)],
+            histogram(&indexed_occurrences[1], Rank::LeastCommon(0))
+        );
+    }
+
+    #[test]
+    fn test_decode_kth() {
+        let indexed_occurrences = count_occurences(INPUT);
+        let decoded = decode(&indexed_occurrences, Rank::MostCommon(1)).unwrap();
+        assert_eq!("deaaaa", decoded.message);
+        assert_eq!(6, decoded.ties.len());
+        assert!(decode(&indexed_occurrences, Rank::MostCommon(26)).is_none());
+    }
+
+    #[test]
+    fn test_decode_ties() {
+        let indexed_occurrences = count_occurences("ab\nbb\nac\n");
+        let decoded = decode(&indexed_occurrences, Rank::MostCommon(0)).unwrap();
+        assert_eq!("ab", decoded.message);
+        assert!(decoded.ties.is_empty());
+
+        let indexed_occurrences = count_occurences("ab\nba\n");
+        let decoded = decode(&indexed_occurrences, Rank::MostCommon(0)).unwrap();
+        assert_eq!("aa", decoded.message);
+        assert_eq!(2, decoded.ties.len());
+        assert_eq!(1, decoded.ties[1].position);
+        assert_eq!(vec!['a', 'b'], decoded.ties[1].candidates);
+
+        let decoded = decode(&indexed_occurrences, Rank::LeastCommon(1)).unwrap();
+        assert_eq!("bb", decoded.message);
+        assert_eq!('b', decoded.ties[0].chosen);
+    }
 }
@@ -1,38 +1,125 @@
+#[macro_use]
+extern crate lazy_static;
+
+use std::env;
+use std::fmt;
 use std::fs;
 
+#[derive(Debug, PartialEq)]
+enum ParseError {
+    UnexpectedClosing(usize),
+    UnclosedOpening(usize),
+    NestedOpening(usize),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::UnexpectedClosing(offset) => {
+                write!(f, "unexpected ']' at offset {}", offset)
+            }
+            ParseError::UnclosedOpening(offset) => {
+                write!(f, "'[' at offset {} is never closed", offset)
+            }
+            ParseError::NestedOpening(offset) => {
+                write!(f, "nested '[' at offset {}", offset)
+            }
+        }
+    }
+}
+
+struct Segment<'a> {
+    sequence: &'a str,
+    offset: usize,
+    depth: usize,
+}
+
 struct AddressIPv7<'a> {
-    supernet_sequences: Vec<&'a str>,
-    hypernet_sequences: Vec<&'a str>,
+    segments: Vec<Segment<'a>>,
 }
 
 impl<'a> AddressIPv7<'a> {
-    fn new(address: &str) -> AddressIPv7 {
-        let opening: Vec<usize> = address.match_indices('[').map(|m| m.0).collect();
-        let closing: Vec<usize> = address.match_indices(']').map(|m| m.0).collect();
-        let braces: Vec<(usize, usize)> = opening.into_iter().zip(closing.into_iter()).collect();
+    fn new(address: &'a str) -> Result<Self, ParseError> {
+        Self::parse(address, false)
+    }
 
-        let hypernet_sequences = braces
-            .iter()
-            .map(|&(start, end)| &address[start + 1..end])
-            .collect::<Vec<&str>>();
+    fn new_nested(address: &'a str) -> Result<Self, ParseError> {
+        Self::parse(address, true)
+    }
 
-        let mut non_braces = Vec::new();
+    // every bracket closes one segment, so empty sequences are kept and Display is lossless
+    fn parse(address: &'a str, allow_nesting: bool) -> Result<Self, ParseError> {
+        let mut segments = Vec::new();
+        let mut openings = Vec::new();
         let mut start = 0;
-        for (open, close) in braces.iter() {
-            non_braces.push((start, *open));
-            start = close + 1;
+
+        for (offset, c) in address.char_indices() {
+            match c {
+                '[' => {
+                    if !allow_nesting && !openings.is_empty() {
+                        return Err(ParseError::NestedOpening(offset));
+                    }
+                    segments.push(Segment {
+                        sequence: &address[start..offset],
+                        offset: start,
+                        depth: openings.len(),
+                    });
+                    openings.push(offset);
+                    start = offset + 1;
+                }
+                ']' => {
+                    if openings.is_empty() {
+                        return Err(ParseError::UnexpectedClosing(offset));
+                    }
+                    segments.push(Segment {
+                        sequence: &address[start..offset],
+                        offset: start,
+                        depth: openings.len(),
+                    });
+                    openings.pop();
+                    start = offset + 1;
+                }
+                _ => {}
+            }
         }
-        non_braces.push((start, address.len()));
 
-        let supernet_sequences = non_braces
-            .iter()
-            .map(|&(start, end)| &address[start..end])
-            .collect::<Vec<&str>>();
+        if let Some(offset) = openings.last() {
+            return Err(ParseError::UnclosedOpening(*offset));
+        }
+        segments.push(Segment {
+            sequence: &address[start..],
+            offset: start,
+            depth: 0,
+        });
 
-        AddressIPv7 {
-            supernet_sequences,
-            hypernet_sequences,
+        Ok(AddressIPv7 { segments })
+    }
+
+    fn supernet_segments(&self) -> impl Iterator<Item = &Segment<'a>> {
+        self.segments.iter().filter(|segment| segment.depth == 0)
+    }
+
+    fn hypernet_segments(&self) -> impl Iterator<Item = &Segment<'a>> {
+        self.segments.iter().filter(|segment| segment.depth > 0)
+    }
+}
+
+impl<'a> fmt::Display for AddressIPv7<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut depth = 0;
+        for (index, segment) in self.segments.iter().enumerate() {
+            if index > 0 {
+                if segment.depth > depth {
+                    write!(f, "[")?;
+                } else {
+                    write!(f, "]")?;
+                }
+            }
+            write!(f, "{}", segment.sequence)?;
+            depth = segment.depth;
         }
+
+        Ok(())
     }
 }
 
@@ -44,46 +131,477 @@ fn part_2(addresses: &[AddressIPv7]) -> usize {
     addresses.iter().filter(supports_ssl).count()
 }
 
-fn supports_tls(address: &&AddressIPv7) -> bool {
-    let has_abba = |sequence: &&str| {
-        sequence.as_bytes().windows(4).any(|window| {
-            window[0] != window[1] && window[0] == window[3] && window[1] == window[2]
-        })
-    };
+#[derive(Debug, PartialEq)]
+struct Evidence<'a> {
+    pattern: &'a str,
+    offset: usize,
+}
 
-    address.supernet_sequences.iter().any(has_abba)
-        && !address.hypernet_sequences.iter().any(has_abba)
+impl<'a> fmt::Display for Evidence<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "'{}' at offset {}", self.pattern, self.offset)
+    }
 }
 
-fn supports_ssl(address: &&AddressIPv7) -> bool {
-    let is_aba = |window: &[u8]| window[0] == window[2] && window[0] != window[1];
-    let is_bab = |super_window: &[u8], hyper_window: &[u8]| {
-        hyper_window[0] == hyper_window[2]
-            && hyper_window[0] != hyper_window[1]
-            && hyper_window[0] == super_window[1]
-            && hyper_window[1] == super_window[0]
-    };
+#[derive(Debug, PartialEq)]
+enum TlsVerdict<'a> {
+    Supported(Evidence<'a>),
+    HypernetAbba(Evidence<'a>),
+    NoAbba,
+}
+
+impl<'a> fmt::Display for TlsVerdict<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TlsVerdict::Supported(abba) => write!(f, "supports TLS: ABBA {} in supernet", abba),
+            TlsVerdict::HypernetAbba(abba) => {
+                write!(f, "no TLS: ABBA {} in hypernet", abba)
+            }
+            TlsVerdict::NoAbba => write!(f, "no TLS: no ABBA in supernet"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum SslVerdict<'a> {
+    Supported {
+        aba: Evidence<'a>,
+        bab: Evidence<'a>,
+    },
+    NoMatch,
+}
 
-    address.supernet_sequences.iter().any(|super_seq| {
-        super_seq.as_bytes().windows(3).any(|super_window| {
-            if is_aba(super_window) {
-                return address.hypernet_sequences.iter().any(|hyper_seq| {
-                    hyper_seq
-                        .as_bytes()
-                        .windows(3)
-                        .any(|hyper_window| is_bab(super_window, hyper_window))
-                });
+impl<'a> fmt::Display for SslVerdict<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SslVerdict::Supported { aba, bab } => {
+                write!(f, "supports SSL: ABA {} matches BAB {}", aba, bab)
             }
-            false
+            SslVerdict::NoMatch => write!(f, "no SSL: no ABA with a matching BAB"),
+        }
+    }
+}
+
+fn windows<'a>(segment: &Segment<'a>, size: usize) -> impl Iterator<Item = Evidence<'a>> {
+    let sequence = segment.sequence;
+    let offset = segment.offset;
+    (0..(sequence.len() + 1).saturating_sub(size)).filter_map(move |i| {
+        sequence.get(i..i + size).map(|pattern| Evidence {
+            pattern,
+            offset: offset + i,
         })
     })
 }
 
+const TLS_RULES: &str = "forbid ABBA in hypernet\n\
+                         require ABBA in supernet";
+const SSL_RULES: &str = "require ABA in supernet with BAB in hypernet";
+
+lazy_static! {
+    static ref TLS: RuleSet = RuleSet::parse(TLS_RULES).unwrap();
+    static ref SSL: RuleSet = RuleSet::parse(SSL_RULES).unwrap();
+}
+
+#[derive(Debug, PartialEq)]
+struct RuleError {
+    line: usize,
+    reason: String,
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.reason)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Scope {
+    Supernet,
+    Hypernet,
+}
+
+impl Scope {
+    fn segments<'a, 'b>(
+        self,
+        address: &'b AddressIPv7<'a>,
+    ) -> Box<dyn Iterator<Item = &'b Segment<'a>> + 'b> {
+        match self {
+            Scope::Supernet => Box::new(address.supernet_segments()),
+            Scope::Hypernet => Box::new(address.hypernet_segments()),
+        }
+    }
+}
+
+// each uppercase letter is a variable standing for one character of the sequence
+#[derive(Debug)]
+struct Pattern {
+    variables: Vec<usize>,
+    distinct: bool,
+}
+
+type Bindings = [Option<u8>; 26];
+
+impl Pattern {
+    fn new(text: &str, distinct: bool) -> Option<Self> {
+        if text.is_empty() || !text.bytes().all(|b| b.is_ascii_uppercase()) {
+            return None;
+        }
+
+        Some(Pattern {
+            variables: text.bytes().map(|b| (b - b'A') as usize).collect(),
+            distinct,
+        })
+    }
+
+    fn bind(&self, window: &[u8]) -> Option<Bindings> {
+        let mut bindings: Bindings = [None; 26];
+        for (&variable, &c) in self.variables.iter().zip(window.iter()) {
+            match bindings[variable] {
+                Some(bound) if bound != c => return None,
+                Some(_) => {}
+                None => {
+                    if self.distinct && bindings.contains(&Some(c)) {
+                        return None;
+                    }
+                    bindings[variable] = Some(c);
+                }
+            }
+        }
+
+        Some(bindings)
+    }
+
+    fn instantiate(&self, bindings: &Bindings) -> Vec<u8> {
+        self.variables
+            .iter()
+            .map(|&variable| bindings[variable].unwrap())
+            .collect()
+    }
+
+    fn find<'a, 'b>(
+        &'b self,
+        address: &'b AddressIPv7<'a>,
+        scope: Scope,
+    ) -> impl Iterator<Item = (Evidence<'a>, Bindings)> + 'b {
+        scope_windows(address, scope, self.variables.len()).filter_map(move |window| {
+            let bindings = self.bind(window.pattern.as_bytes())?;
+            Some((window, bindings))
+        })
+    }
+}
+
+#[derive(Debug)]
+enum Clause {
+    Require(Pattern, Scope),
+    Forbid(Pattern, Scope),
+    // the partner uses the same variables, bound by the match of the first pattern
+    Paired(Pattern, Scope, Pattern, Scope),
+}
+
+#[derive(Debug, PartialEq)]
+enum Outcome<'a> {
+    Satisfied(Vec<Evidence<'a>>),
+    Violated {
+        clause: usize,
+        evidence: Option<Evidence<'a>>,
+    },
+}
+
+#[derive(Debug)]
+struct RuleSet {
+    clauses: Vec<Clause>,
+}
+
+impl RuleSet {
+    // require [loose] PATTERN in SCOPE [with PATTERN in SCOPE]
+    // forbid [loose] PATTERN in SCOPE
+    fn parse(rules: &str) -> Result<Self, RuleError> {
+        let mut clauses = Vec::new();
+        for (index, line) in rules.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let error = |reason: &str| RuleError {
+                line: index + 1,
+                reason: String::from(reason),
+            };
+            let mut words = line.split_whitespace().peekable();
+            let keyword = words.next().unwrap();
+            let distinct = if words.peek() == Some(&"loose") {
+                words.next();
+                false
+            } else {
+                true
+            };
+
+            let pattern_in_scope = |words: &mut dyn Iterator<Item = &str>| {
+                let pattern = words.next().ok_or_else(|| error("missing pattern"))?;
+                let pattern = Pattern::new(pattern, distinct)
+                    .ok_or_else(|| error("patterns must consist of uppercase letters"))?;
+                if words.next() != Some("in") {
+                    return Err(error("expected 'in' after pattern"));
+                }
+                let scope = match words.next() {
+                    Some("supernet") => Scope::Supernet,
+                    Some("hypernet") => Scope::Hypernet,
+                    _ => return Err(error("expected 'supernet' or 'hypernet'")),
+                };
+                Ok((pattern, scope))
+            };
+
+            let (pattern, scope) = pattern_in_scope(&mut words)?;
+            let clause = match (keyword, words.next()) {
+                ("require", None) => Clause::Require(pattern, scope),
+                ("forbid", None) => Clause::Forbid(pattern, scope),
+                ("require", Some("with")) => {
+                    let (partner, partner_scope) = pattern_in_scope(&mut words)?;
+                    if partner
+                        .variables
+                        .iter()
+                        .any(|variable| !pattern.variables.contains(variable))
+                    {
+                        return Err(error("partner pattern uses an unbound letter"));
+                    }
+                    Clause::Paired(pattern, scope, partner, partner_scope)
+                }
+                ("require", _) | ("forbid", _) => return Err(error("unexpected trailing words")),
+                _ => return Err(error("rules start with 'require' or 'forbid'")),
+            };
+            if words.next().is_some() {
+                return Err(error("unexpected trailing words"));
+            }
+
+            clauses.push(clause);
+        }
+
+        Ok(RuleSet { clauses })
+    }
+
+    fn evaluate<'a>(&self, address: &AddressIPv7<'a>) -> Outcome<'a> {
+        let mut evidence = Vec::new();
+        for (index, clause) in self.clauses.iter().enumerate() {
+            let violation = |found| Outcome::Violated {
+                clause: index,
+                evidence: found,
+            };
+            match clause {
+                Clause::Require(pattern, scope) => match pattern.find(address, *scope).next() {
+                    Some((found, _)) => evidence.push(found),
+                    None => return violation(None),
+                },
+                Clause::Forbid(pattern, scope) => {
+                    if let Some((found, _)) = pattern.find(address, *scope).next() {
+                        return violation(Some(found));
+                    }
+                }
+                Clause::Paired(pattern, scope, partner, partner_scope) => {
+                    let pair = pattern.find(address, *scope).find_map(|(found, bindings)| {
+                        let wanted = partner.instantiate(&bindings);
+                        scope_windows(address, *partner_scope, wanted.len())
+                            .find(|window| window.pattern.as_bytes() == wanted.as_slice())
+                            .map(|partner_found| (found, partner_found))
+                    });
+                    match pair {
+                        Some((found, partner_found)) => {
+                            evidence.push(found);
+                            evidence.push(partner_found);
+                        }
+                        None => return violation(None),
+                    }
+                }
+            }
+        }
+
+        Outcome::Satisfied(evidence)
+    }
+}
+
+fn scope_windows<'a, 'b>(
+    address: &'b AddressIPv7<'a>,
+    scope: Scope,
+    size: usize,
+) -> impl Iterator<Item = Evidence<'a>> + 'b {
+    scope
+        .segments(address)
+        .flat_map(move |segment| windows(segment, size))
+}
+
+fn explain_tls<'a>(address: &AddressIPv7<'a>) -> TlsVerdict<'a> {
+    match TLS.evaluate(address) {
+        Outcome::Satisfied(mut evidence) => TlsVerdict::Supported(evidence.remove(0)),
+        Outcome::Violated {
+            evidence: Some(abba),
+            ..
+        } => TlsVerdict::HypernetAbba(abba),
+        Outcome::Violated { evidence: None, .. } => TlsVerdict::NoAbba,
+    }
+}
+
+fn explain_ssl<'a>(address: &AddressIPv7<'a>) -> SslVerdict<'a> {
+    match SSL.evaluate(address) {
+        Outcome::Satisfied(mut evidence) => {
+            let bab = evidence.pop().unwrap();
+            let aba = evidence.pop().unwrap();
+            SslVerdict::Supported { aba, bab }
+        }
+        Outcome::Violated { .. } => SslVerdict::NoMatch,
+    }
+}
+
+fn supports_tls(address: &&AddressIPv7) -> bool {
+    matches!(explain_tls(address), TlsVerdict::Supported(_))
+}
+
+fn supports_ssl(address: &&AddressIPv7) -> bool {
+    matches!(explain_ssl(address), SslVerdict::Supported { .. })
+}
+
+// only the property tests generate addresses
+#[cfg(test)]
+mod generator {
+    use rand::Rng;
+    use std::ops::RangeInclusive;
+
+    #[derive(Copy, Clone, Debug)]
+    pub enum Policy {
+        Tls,
+        Ssl,
+        Both,
+        Neither,
+    }
+
+    pub struct AddressGenerator {
+        hypernets: RangeInclusive<usize>,
+        lengths: RangeInclusive<usize>,
+    }
+
+    // each segment hosts at most one planted pattern; the rest is filler in which every letter
+    // differs from the two before it and from the planted letters, so no ABA or ABBA appears by accident
+    impl AddressGenerator {
+        pub fn new(hypernets: RangeInclusive<usize>, lengths: RangeInclusive<usize>) -> Self {
+            AddressGenerator { hypernets, lengths }
+        }
+
+        pub fn generate<R: Rng>(&self, policy: Policy, rng: &mut R) -> Option<String> {
+            let min_hypernets = match policy {
+                Policy::Ssl | Policy::Both => 1,
+                Policy::Tls | Policy::Neither => 0,
+            };
+            if *self.hypernets.end() < min_hypernets || self.lengths.is_empty() {
+                return None;
+            }
+
+            let hypernets =
+                rng.gen_range(min_hypernets.max(*self.hypernets.start())..=*self.hypernets.end());
+            let mut supernet_plants: Vec<Option<Vec<u8>>> = vec![None; hypernets + 1];
+            let mut hypernet_plants: Vec<Option<Vec<u8>>> = vec![None; hypernets];
+
+            let (x, y) = two_letters(rng);
+            let abba = vec![x, y, y, x];
+            let aba = vec![x, y, x];
+            let bab = vec![y, x, y];
+            let supernet = rng.gen_range(0..supernet_plants.len());
+            match policy {
+                Policy::Tls => supernet_plants[supernet] = Some(abba),
+                Policy::Ssl => {
+                    supernet_plants[supernet] = Some(aba);
+                    let hypernet = rng.gen_range(0..hypernets);
+                    hypernet_plants[hypernet] = Some(bab);
+                    if hypernets > 1 {
+                        let (p, q) = two_letters(rng);
+                        let other = (hypernet + rng.gen_range(1..hypernets)) % hypernets;
+                        hypernet_plants[other] = Some(vec![p, q, q, p]);
+                    }
+                }
+                Policy::Both => {
+                    let other = (supernet + rng.gen_range(1..=hypernets)) % (hypernets + 1);
+                    supernet_plants[supernet] = Some(abba);
+                    supernet_plants[other] = Some(aba);
+                    hypernet_plants[rng.gen_range(0..hypernets)] = Some(bab);
+                }
+                Policy::Neither => {
+                    if hypernets > 0 {
+                        let hypernet = rng.gen_range(0..hypernets);
+                        if rng.gen() {
+                            supernet_plants[supernet] = Some(abba.clone());
+                            hypernet_plants[hypernet] = Some(abba);
+                        } else {
+                            supernet_plants[supernet] = Some(aba.clone());
+                            hypernet_plants[hypernet] = Some(aba);
+                        }
+                    } else if rng.gen() {
+                        supernet_plants[supernet] = Some(aba);
+                    }
+                }
+            }
+
+            let mut address = String::new();
+            for (index, plant) in supernet_plants.iter().enumerate() {
+                if index > 0 {
+                    address.push('[');
+                    address.push_str(&self.segment(hypernet_plants[index - 1].as_deref(), rng));
+                    address.push(']');
+                }
+                address.push_str(&self.segment(plant.as_deref(), rng));
+            }
+
+            Some(address)
+        }
+
+        fn segment<R: Rng>(&self, plant: Option<&[u8]>, rng: &mut R) -> String {
+            let plant = plant.unwrap_or(&[]);
+            let length = rng.gen_range(self.lengths.clone()).max(plant.len());
+            let position = rng.gen_range(0..=length - plant.len());
+
+            let mut segment: Vec<u8> = Vec::with_capacity(length);
+            while segment.len() < length {
+                if segment.len() == position && !plant.is_empty() {
+                    segment.extend_from_slice(plant);
+                    continue;
+                }
+                let previous = &segment[segment.len().saturating_sub(2)..];
+                let letter = loop {
+                    let letter = rng.gen_range(b'a'..=b'z');
+                    if !previous.contains(&letter) && !plant.contains(&letter) {
+                        break letter;
+                    }
+                };
+                segment.push(letter);
+            }
+
+            String::from_utf8(segment).unwrap()
+        }
+    }
+
+    fn two_letters<R: Rng>(rng: &mut R) -> (u8, u8) {
+        let x = rng.gen_range(b'a'..=b'z');
+        let y = (x - b'a' + rng.gen_range(1..26)) % 26 + b'a';
+        (x, y)
+    }
+}
+
 fn main() {
     let input = fs::read_to_string("input").expect("file not found");
     let input = input.trim();
 
-    let addresses: Vec<AddressIPv7> = input.lines().map(AddressIPv7::new).collect();
+    // `cargo run -- nested` parses the addresses with hypernets allowed inside hypernets,
+    // which the puzzle input does not use, so the answers stay the same
+    let parse = if env::args().nth(1).as_deref() == Some("nested") {
+        AddressIPv7::new_nested
+    } else {
+        AddressIPv7::new
+    };
+    let addresses: Vec<AddressIPv7> = input
+        .lines()
+        .map(parse)
+        .collect::<Result<_, _>>()
+        .expect("invalid address");
+    assert!(input
+        .lines()
+        .zip(addresses.iter())
+        .all(|(line, address)| line == address.to_string()));
 
     assert_eq!(105, part_1(&addresses));
     assert_eq!(258, part_2(&addresses));
@@ -91,21 +609,269 @@ fn main() {
 
 #[cfg(test)]
 mod tests {
+    use super::generator::*;
     use super::*;
+    use proptest::prelude::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn check_generated(policy: Policy, seed: u64, hypernets: usize, length: usize) {
+        let generator = AddressGenerator::new(0..=hypernets, 0..=length);
+        let generated = generator
+            .generate(policy, &mut StdRng::seed_from_u64(seed))
+            .unwrap();
+        let address = AddressIPv7::new(&generated).unwrap();
+        assert_eq!(generated, address.to_string());
+
+        let tls = matches!(policy, Policy::Tls | Policy::Both);
+        let ssl = matches!(policy, Policy::Ssl | Policy::Both);
+        assert_eq!(tls, supports_tls(&&address), "{}", generated);
+        assert_eq!(ssl, supports_ssl(&&address), "{}", generated);
+    }
+
+    proptest! {
+        #[test]
+        fn prop_generated_tls(seed: u64, hypernets in 0..6usize, length in 0..20usize) {
+            check_generated(Policy::Tls, seed, hypernets, length);
+        }
+
+        #[test]
+        fn prop_generated_ssl(seed: u64, hypernets in 1..6usize, length in 0..20usize) {
+            check_generated(Policy::Ssl, seed, hypernets, length);
+        }
+
+        #[test]
+        fn prop_generated_both(seed: u64, hypernets in 1..6usize, length in 0..20usize) {
+            check_generated(Policy::Both, seed, hypernets, length);
+        }
+
+        #[test]
+        fn prop_generated_neither(seed: u64, hypernets in 0..6usize, length in 0..20usize) {
+            check_generated(Policy::Neither, seed, hypernets, length);
+        }
+    }
+
+    #[test]
+    fn test_generator_infeasible() {
+        let generator = AddressGenerator::new(0..=0, 4..=8);
+        let mut rng = StdRng::seed_from_u64(0);
+        assert!(generator.generate(Policy::Ssl, &mut rng).is_none());
+        assert!(generator.generate(Policy::Both, &mut rng).is_none());
+        assert!(generator.generate(Policy::Tls, &mut rng).is_some());
+    }
 
     #[test]
     fn test_supports_tls() {
-        assert!(supports_tls(&&AddressIPv7::new("abba[mnop]qrst")));
-        assert!(!supports_tls(&&AddressIPv7::new("abcd[bddb]xyyx")));
-        assert!(!supports_tls(&&AddressIPv7::new("aaaa[qwer]tyui")));
-        assert!(supports_tls(&&AddressIPv7::new("ioxxoj[asdfgh]zxcvbn")));
+        assert!(supports_tls(&&AddressIPv7::new("abba[mnop]qrst").unwrap()));
+        assert!(!supports_tls(&&AddressIPv7::new("abcd[bddb]xyyx").unwrap()));
+        assert!(!supports_tls(&&AddressIPv7::new("aaaa[qwer]tyui").unwrap()));
+        assert!(supports_tls(
+            &&AddressIPv7::new("ioxxoj[asdfgh]zxcvbn").unwrap()
+        ));
     }
 
     #[test]
     fn test_supports_ssl() {
-        assert!(supports_ssl(&&AddressIPv7::new("aba[bab]xyz")));
-        assert!(!supports_ssl(&&AddressIPv7::new("xyx[xyx]xyx")));
-        assert!(supports_ssl(&&AddressIPv7::new("aaa[kek]eke")));
-        assert!(supports_ssl(&&AddressIPv7::new("zazbz[bzb]cdb")));
+        assert!(supports_ssl(&&AddressIPv7::new("aba[bab]xyz").unwrap()));
+        assert!(!supports_ssl(&&AddressIPv7::new("xyx[xyx]xyx").unwrap()));
+        assert!(supports_ssl(&&AddressIPv7::new("aaa[kek]eke").unwrap()));
+        assert!(supports_ssl(&&AddressIPv7::new("zazbz[bzb]cdb").unwrap()));
+    }
+
+    #[test]
+    fn test_explain_tls() {
+        let address = AddressIPv7::new("abba[mnop]qrst").unwrap();
+        assert_eq!(
+            TlsVerdict::Supported(Evidence {
+                pattern: "abba",
+                offset: 0
+            }),
+            explain_tls(&address)
+        );
+
+        let address = AddressIPv7::new("abcd[bddb]xyyx").unwrap();
+        assert_eq!(
+            TlsVerdict::HypernetAbba(Evidence {
+                pattern: "bddb",
+                offset: 5
+            }),
+            explain_tls(&address)
+        );
+
+        let address = AddressIPv7::new("aaaa[qwer]tyui").unwrap();
+        assert_eq!(TlsVerdict::NoAbba, explain_tls(&address));
+
+        let address = AddressIPv7::new("ioxxoj[asdfgh]zxcvbn").unwrap();
+        assert_eq!(
+            "supports TLS: ABBA 'oxxo' at offset 1 in supernet",
+            explain_tls(&address).to_string()
+        );
+    }
+
+    #[test]
+    fn test_explain_ssl() {
+        let address = AddressIPv7::new("zazbz[bzb]cdb").unwrap();
+        assert_eq!(
+            SslVerdict::Supported {
+                aba: Evidence {
+                    pattern: "zbz",
+                    offset: 2
+                },
+                bab: Evidence {
+                    pattern: "bzb",
+                    offset: 6
+                }
+            },
+            explain_ssl(&address)
+        );
+
+        assert_eq!(
+            "supports SSL: ABA 'zbz' at offset 2 matches BAB 'bzb' at offset 6",
+            explain_ssl(&address).to_string()
+        );
+
+        let address = AddressIPv7::new("xyx[xyx]xyx").unwrap();
+        assert_eq!(SslVerdict::NoMatch, explain_ssl(&address));
+        assert_eq!(
+            "no SSL: no ABA with a matching BAB",
+            explain_ssl(&address).to_string()
+        );
+    }
+
+    #[test]
+    fn test_rule_set() {
+        let rules = RuleSet::parse(
+            "# palindromes of five with a distinct middle\n\
+             require ABCBA in supernet\n\
+             forbid loose AA in hypernet",
+        )
+        .unwrap();
+
+        let address = AddressIPv7::new("xabcbay[abab]").unwrap();
+        assert_eq!(
+            Outcome::Satisfied(vec![Evidence {
+                pattern: "abcba",
+                offset: 1
+            }]),
+            rules.evaluate(&address)
+        );
+
+        let address = AddressIPv7::new("abbba[xy]").unwrap();
+        assert_eq!(
+            Outcome::Violated {
+                clause: 0,
+                evidence: None
+            },
+            rules.evaluate(&address)
+        );
+
+        let address = AddressIPv7::new("abcba[xyyz]").unwrap();
+        assert_eq!(
+            Outcome::Violated {
+                clause: 1,
+                evidence: Some(Evidence {
+                    pattern: "yy",
+                    offset: 7
+                })
+            },
+            rules.evaluate(&address)
+        );
+    }
+
+    #[test]
+    fn test_rule_set_mirrored() {
+        let rules = RuleSet::parse("require ABCD in hypernet with DCBA in supernet").unwrap();
+        assert!(matches!(
+            rules.evaluate(&AddressIPv7::new("xdcbax[abcd]").unwrap()),
+            Outcome::Satisfied(_)
+        ));
+        assert!(matches!(
+            rules.evaluate(&AddressIPv7::new("xdcbax[abcc]").unwrap()),
+            Outcome::Violated { .. }
+        ));
+    }
+
+    #[test]
+    fn test_rule_set_errors() {
+        let error = |rules| RuleSet::parse(rules).unwrap_err();
+        assert_eq!(
+            2,
+            error("require ABBA in supernet\nallow ABBA in hypernet").line
+        );
+        assert_eq!(
+            "line 1: patterns must consist of uppercase letters",
+            error("require abba in supernet").to_string()
+        );
+        assert_eq!(
+            "line 1: expected 'supernet' or 'hypernet'",
+            error("forbid ABBA in overnet").to_string()
+        );
+        assert_eq!(
+            "line 1: partner pattern uses an unbound letter",
+            error("require ABA in supernet with BCB in hypernet").to_string()
+        );
+        assert_eq!(
+            "line 1: unexpected trailing words",
+            error("forbid ABA in supernet with BAB in hypernet").to_string()
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            Some(ParseError::UnexpectedClosing(4)),
+            AddressIPv7::new("abba]mnop[qrst").err()
+        );
+        assert_eq!(
+            Some(ParseError::UnclosedOpening(9)),
+            AddressIPv7::new("abba[mn]o[pqrst").err()
+        );
+        assert_eq!(
+            Some(ParseError::NestedOpening(7)),
+            AddressIPv7::new("abba[mn[op]]qrst").err()
+        );
+        assert_eq!(
+            Some(ParseError::UnexpectedClosing(12)),
+            AddressIPv7::new_nested("abba[mn[op]]]qrst").err()
+        );
+    }
+
+    #[test]
+    fn test_nested() {
+        let address = AddressIPv7::new_nested("abba[mn[xyyx]op]qrst").unwrap();
+        assert_eq!(
+            vec!["abba", "qrst"],
+            address
+                .supernet_segments()
+                .map(|s| s.sequence)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec!["mn", "xyyx", "op"],
+            address
+                .hypernet_segments()
+                .map(|s| s.sequence)
+                .collect::<Vec<_>>()
+        );
+        assert!(!supports_tls(&&address));
+    }
+
+    #[test]
+    fn test_display() {
+        for address in &[
+            "abba[mnop]qrst",
+            "[abba]",
+            "a[]b[][c]",
+            "",
+            "ioxxoj[asdfgh]zxcvbn[qwer]",
+        ] {
+            assert_eq!(*address, AddressIPv7::new(address).unwrap().to_string());
+        }
+        for address in &["a[b[c]d]e", "[[[]]]", "x[[y]z][w]"] {
+            assert_eq!(
+                *address,
+                AddressIPv7::new_nested(address).unwrap().to_string()
+            );
+        }
     }
 }
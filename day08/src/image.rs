@@ -0,0 +1,408 @@
+use crate::screen::{Pixel, Screen};
+use std::io::{self, Write};
+use std::iter;
+use std::time::Duration;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Color(pub u8, pub u8, pub u8);
+
+impl Color {
+    fn luma(self) -> u8 {
+        ((299 * self.0 as u32 + 587 * self.1 as u32 + 114 * self.2 as u32) / 1000) as u8
+    }
+
+    fn hex(self) -> String {
+        format!("#{:02x}{:02x}{:02x}", self.0, self.1, self.2)
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Style {
+    pub scale: usize,
+    pub on: Color,
+    pub off: Color,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Style {
+            scale: 1,
+            on: Color(0, 0, 0),
+            off: Color(255, 255, 255),
+        }
+    }
+}
+
+fn dimensions(screen: &Screen, style: &Style) -> (usize, usize) {
+    (screen.width() * style.scale, screen.height() * style.scale)
+}
+
+fn scaled_rows<'a>(screen: &'a Screen, style: &Style) -> impl Iterator<Item = Vec<Pixel>> + 'a {
+    let scale = style.scale;
+    screen.rows().flat_map(move |row| {
+        let scaled: Vec<Pixel> = row
+            .iter()
+            .flat_map(|pixel| iter::repeat_n(*pixel, scale))
+            .collect();
+        iter::repeat_n(scaled, scale)
+    })
+}
+
+fn pack_bits(row: &[Pixel]) -> Vec<u8> {
+    row.chunks(8)
+        .map(|bits| {
+            bits.iter()
+                .enumerate()
+                .filter(|(_, pixel)| **pixel == Pixel::On)
+                .fold(0, |byte, (i, _)| byte | 0x80 >> i)
+        })
+        .collect()
+}
+
+// binary PBM marks lit pixels with 1, which viewers show as black; colors are ignored
+pub fn write_pbm<W: Write>(screen: &Screen, style: &Style, writer: &mut W) -> io::Result<()> {
+    let (width, height) = dimensions(screen, style);
+    write!(writer, "P4\n{} {}\n", width, height)?;
+    for row in scaled_rows(screen, style) {
+        writer.write_all(&pack_bits(&row))?;
+    }
+
+    Ok(())
+}
+
+pub fn write_pgm<W: Write>(screen: &Screen, style: &Style, writer: &mut W) -> io::Result<()> {
+    let (width, height) = dimensions(screen, style);
+    write!(writer, "P5\n{} {}\n255\n", width, height)?;
+    for row in scaled_rows(screen, style) {
+        let gray: Vec<u8> = row
+            .iter()
+            .map(|pixel| match pixel {
+                Pixel::On => style.on.luma(),
+                Pixel::Off => style.off.luma(),
+            })
+            .collect();
+        writer.write_all(&gray)?;
+    }
+
+    Ok(())
+}
+
+pub fn write_png<W: Write>(screen: &Screen, style: &Style, writer: &mut W) -> io::Result<()> {
+    let (width, height) = dimensions(screen, style);
+    writer.write_all(PNG_SIGNATURE)?;
+    write_png_chunk(writer, b"IHDR", &png_header(width, height))?;
+    write_png_chunk(writer, b"PLTE", &png_palette(style))?;
+    write_png_chunk(writer, b"IDAT", &zlib_stored(&png_scanlines(screen, style)))?;
+    write_png_chunk(writer, b"IEND", &[])
+}
+
+// every frame covers the whole canvas and replaces the previous one
+pub fn write_apng<W: Write>(
+    screens: &[&Screen],
+    style: &Style,
+    delay: Duration,
+    writer: &mut W,
+) -> io::Result<()> {
+    let (width, height) = match screens.first() {
+        Some(screen) => dimensions(screen, style),
+        None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "no frames")),
+    };
+    if screens
+        .iter()
+        .any(|screen| dimensions(screen, style) != (width, height))
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "frames differ in size",
+        ));
+    }
+
+    writer.write_all(PNG_SIGNATURE)?;
+    write_png_chunk(writer, b"IHDR", &png_header(width, height))?;
+    let mut animation_control = Vec::with_capacity(8);
+    animation_control.extend_from_slice(&(screens.len() as u32).to_be_bytes());
+    animation_control.extend_from_slice(&0u32.to_be_bytes());
+    write_png_chunk(writer, b"acTL", &animation_control)?;
+    write_png_chunk(writer, b"PLTE", &png_palette(style))?;
+
+    let delay = delay.as_millis().min(u16::MAX as u128) as u16;
+    let mut sequence = 0u32;
+    for (index, screen) in screens.iter().enumerate() {
+        let mut frame_control = Vec::with_capacity(26);
+        frame_control.extend_from_slice(&sequence.to_be_bytes());
+        frame_control.extend_from_slice(&(width as u32).to_be_bytes());
+        frame_control.extend_from_slice(&(height as u32).to_be_bytes());
+        frame_control.extend_from_slice(&[0; 8]);
+        frame_control.extend_from_slice(&delay.to_be_bytes());
+        frame_control.extend_from_slice(&1000u16.to_be_bytes());
+        frame_control.extend_from_slice(&[0, 0]);
+        write_png_chunk(writer, b"fcTL", &frame_control)?;
+        sequence += 1;
+
+        let data = zlib_stored(&png_scanlines(screen, style));
+        if index == 0 {
+            write_png_chunk(writer, b"IDAT", &data)?;
+        } else {
+            let mut frame_data = sequence.to_be_bytes().to_vec();
+            frame_data.extend_from_slice(&data);
+            write_png_chunk(writer, b"fdAT", &frame_data)?;
+            sequence += 1;
+        }
+    }
+
+    write_png_chunk(writer, b"IEND", &[])
+}
+
+pub fn write_svg<W: Write>(screen: &Screen, style: &Style, writer: &mut W) -> io::Result<()> {
+    let (width, height) = dimensions(screen, style);
+    writeln!(
+        writer,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{1}" viewBox="0 0 {0} {1}" shape-rendering="crispEdges">"#,
+        width, height
+    )?;
+    writeln!(
+        writer,
+        r#"<rect width="{}" height="{}" fill="{}"/>"#,
+        width,
+        height,
+        style.off.hex()
+    )?;
+    for (y, row) in screen.rows().enumerate() {
+        for (x, pixel) in row.iter().enumerate() {
+            if *pixel == Pixel::On {
+                writeln!(
+                    writer,
+                    r#"<rect x="{}" y="{}" width="{3}" height="{3}" fill="{2}"/>"#,
+                    x * style.scale,
+                    y * style.scale,
+                    style.on.hex(),
+                    style.scale
+                )?;
+            }
+        }
+    }
+    writeln!(writer, "</svg>")
+}
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+fn png_header(width: usize, height: usize) -> Vec<u8> {
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // 1 bit per pixel indexing the palette, default compression, filter and no interlacing
+    header.extend_from_slice(&[1, 3, 0, 0, 0]);
+    header
+}
+
+fn png_palette(style: &Style) -> Vec<u8> {
+    vec![
+        style.off.0,
+        style.off.1,
+        style.off.2,
+        style.on.0,
+        style.on.1,
+        style.on.2,
+    ]
+}
+
+fn png_scanlines(screen: &Screen, style: &Style) -> Vec<u8> {
+    let mut data = Vec::new();
+    for row in scaled_rows(screen, style) {
+        data.push(0);
+        data.extend(pack_bits(&row));
+    }
+    data
+}
+
+fn write_png_chunk<W: Write>(writer: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    writer.write_all(kind)?;
+    writer.write_all(data)?;
+    let crc = crc32(kind.iter().chain(data.iter()));
+    writer.write_all(&crc.to_be_bytes())
+}
+
+fn crc32<'a>(bytes: impl Iterator<Item = &'a u8>) -> u32 {
+    let crc = bytes.fold(0xffff_ffff, |crc: u32, byte| {
+        (0..8).fold(crc ^ *byte as u32, |crc, _| {
+            if crc & 1 == 1 {
+                0xedb8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            }
+        })
+    });
+    crc ^ 0xffff_ffff
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (a, b) = bytes.iter().fold((1u32, 0u32), |(a, b), byte| {
+        let a = (a + *byte as u32) % 65_521;
+        (a, (b + a) % 65_521)
+    });
+    b << 16 | a
+}
+
+// deflate with uncompressed blocks only, which every decoder accepts
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut stream = vec![0x78, 0x01];
+    let mut blocks = data.chunks(65_535).peekable();
+    if blocks.peek().is_none() {
+        stream.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let length = block.len() as u16;
+        stream.push(last as u8);
+        stream.extend_from_slice(&length.to_le_bytes());
+        stream.extend_from_slice(&(!length).to_le_bytes());
+        stream.extend_from_slice(block);
+    }
+    stream.extend_from_slice(&adler32(data).to_be_bytes());
+    stream
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn screen() -> Screen {
+        "#.#\n.#.\n".parse().unwrap()
+    }
+
+    #[test]
+    fn test_write_pbm() {
+        let mut output = Vec::new();
+        write_pbm(&screen(), &Style::default(), &mut output).unwrap();
+        assert_eq!(b"P4\n3 2\n\xa0\x40".to_vec(), output);
+
+        let style = Style {
+            scale: 3,
+            ..Style::default()
+        };
+        let mut output = Vec::new();
+        write_pbm(&screen(), &style, &mut output).unwrap();
+        assert_eq!(b"P4\n9 6\n".len() + 6 * 2, output.len());
+        assert_eq!(&[0xe3, 0x80], &output[7..9]);
+    }
+
+    #[test]
+    fn test_write_pgm() {
+        let style = Style {
+            scale: 1,
+            on: Color(255, 255, 255),
+            off: Color(0, 0, 0),
+        };
+        let mut output = Vec::new();
+        write_pgm(&screen(), &style, &mut output).unwrap();
+        assert_eq!(b"P5\n3 2\n255\n\xff\x00\xff\x00\xff\x00".to_vec(), output);
+    }
+
+    #[test]
+    fn test_write_png() {
+        let mut output = Vec::new();
+        write_png(&screen(), &Style::default(), &mut output).unwrap();
+        assert_eq!(PNG_SIGNATURE, &output[0..8]);
+        assert_eq!(b"IHDR", &output[12..16]);
+        assert_eq!(&[0, 0, 0, 3, 0, 0, 0, 2, 1, 3], &output[16..26]);
+        assert_eq!(b"PLTE\xff\xff\xff\x00\x00\x00", &output[37..47]);
+        assert_eq!(b"IEND\xae\x42\x60\x82", &output[output.len() - 8..]);
+    }
+
+    #[test]
+    fn test_png_scanlines() {
+        let screen: Screen = "#.#.#.#.#\n.........\n".parse().unwrap();
+        assert_eq!(
+            vec![0, 0xaa, 0x80, 0, 0, 0],
+            png_scanlines(&screen, &Style::default())
+        );
+    }
+
+    #[test]
+    fn test_write_apng() {
+        let first = screen();
+        let second: Screen = "...\n###\n".parse().unwrap();
+        let mut output = Vec::new();
+        write_apng(
+            &[&first, &second, &first],
+            &Style::default(),
+            Duration::from_millis(250),
+            &mut output,
+        )
+        .unwrap();
+
+        let chunks: Vec<&[u8]> = output
+            .windows(4)
+            .filter(|window| {
+                [
+                    b"IHDR", b"acTL", b"PLTE", b"fcTL", b"IDAT", b"fdAT", b"IEND",
+                ]
+                .iter()
+                .any(|kind| &kind[..] == *window)
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                &b"IHDR"[..],
+                b"acTL",
+                b"PLTE",
+                b"fcTL",
+                b"IDAT",
+                b"fcTL",
+                b"fdAT",
+                b"fcTL",
+                b"fdAT",
+                b"IEND"
+            ],
+            chunks
+        );
+        assert_eq!(&[0, 0, 0, 3, 0, 0, 0, 0], &output[41..49]);
+
+        let mismatched: Screen = "..\n".parse().unwrap();
+        assert!(write_apng(
+            &[&first, &mismatched],
+            &Style::default(),
+            Duration::from_millis(250),
+            &mut Vec::new()
+        )
+        .is_err());
+        assert!(write_apng(
+            &[],
+            &Style::default(),
+            Duration::from_millis(250),
+            &mut Vec::new()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_checksums() {
+        assert_eq!(0xcbf4_3926, crc32(b"123456789".iter()));
+        assert_eq!(0x11e6_0398, adler32(b"Wikipedia"));
+    }
+
+    #[test]
+    fn test_zlib_stored() {
+        let stream = zlib_stored(b"abc");
+        assert_eq!(&[0x78, 0x01, 1, 3, 0, 0xfc, 0xff], &stream[0..7]);
+        assert_eq!(b"abc", &stream[7..10]);
+        assert_eq!(2 + 5 + 3 + 4, stream.len());
+    }
+
+    #[test]
+    fn test_write_svg() {
+        let style = Style {
+            scale: 10,
+            on: Color(255, 0, 0),
+            off: Color(0, 0, 16),
+        };
+        let mut output = Vec::new();
+        write_svg(&screen(), &style, &mut output).unwrap();
+        let svg = String::from_utf8(output).unwrap();
+        assert!(svg.contains(r#"width="30" height="20""#));
+        assert!(svg.contains(r##"<rect width="30" height="20" fill="#000010"/>"##));
+        assert!(svg.contains(r##"<rect x="10" y="10" width="10" height="10" fill="#ff0000"/>"##));
+        assert_eq!(3, svg.matches("#ff0000").count());
+    }
+}
@@ -0,0 +1,242 @@
+use crate::screen::{Pixel, Screen};
+use crate::{execute, parse_instruction, Instruction, InstructionError};
+
+pub struct Entry {
+    pub instruction: Instruction,
+    // pixels the inverse instruction cannot bring back, with their value before the instruction ran
+    pub overwritten: Vec<(usize, usize, Pixel)>,
+}
+
+#[derive(Default)]
+pub struct Journal {
+    entries: Vec<Entry>,
+}
+
+// rect, rect-off and clear have no inverse; a shift is undone by shifting back and
+// restoring whatever fell off the edge from the journal
+fn inverse(screen: &Screen, instruction: Instruction) -> Option<Instruction> {
+    let (width, height) = (screen.width() as isize, screen.height() as isize);
+    match instruction {
+        Instruction::Rect { .. } | Instruction::RectOff { .. } | Instruction::Clear => None,
+        Instruction::Invert | Instruction::FlipRow(_) | Instruction::FlipColumn(_) => {
+            Some(instruction)
+        }
+        Instruction::RotateRow { row, count } => Some(Instruction::RotateRow {
+            row,
+            count: -count.rem_euclid(width),
+        }),
+        Instruction::RotateColumn { column, count } => Some(Instruction::RotateColumn {
+            column,
+            count: -count.rem_euclid(height),
+        }),
+        Instruction::ShiftRow { row, count } => Some(Instruction::ShiftRow {
+            row,
+            count: -count.clamp(-width, width),
+        }),
+        Instruction::ShiftColumn { column, count } => Some(Instruction::ShiftColumn {
+            column,
+            count: -count.clamp(-height, height),
+        }),
+    }
+}
+
+// the only pixels the inverse can get wrong: the rect, the cells a shift pushes off the
+// screen, or everything for clear; empty for an instruction that does not fit
+fn area(screen: &Screen, instruction: Instruction) -> Vec<(usize, usize)> {
+    let rect = |width: usize, height: usize| -> Vec<(usize, usize)> {
+        (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .collect()
+    };
+    // positions along a line of `length` that a shift by `count` drops
+    let dropped = |length: usize, count: isize| {
+        let n = count.unsigned_abs().min(length);
+        if count > 0 {
+            length - n..length
+        } else {
+            0..n
+        }
+    };
+    let fits = |width: usize, height: usize| width <= screen.width() && height <= screen.height();
+    match instruction {
+        Instruction::Rect { width, height } | Instruction::RectOff { width, height }
+            if fits(width, height) =>
+        {
+            rect(width, height)
+        }
+        Instruction::Clear => rect(screen.width(), screen.height()),
+        Instruction::ShiftRow { row, count } if row < screen.height() => {
+            dropped(screen.width(), count).map(|x| (x, row)).collect()
+        }
+        Instruction::ShiftColumn { column, count } if column < screen.width() => {
+            dropped(screen.height(), count)
+                .map(|y| (column, y))
+                .collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+fn undo_entry(screen: &mut Screen, entry: &Entry) {
+    if let Some(inverse) = inverse(screen, entry.instruction) {
+        execute(screen, inverse).expect("journaled instructions fit on the screen");
+    }
+    for &(x, y, pixel) in entry.overwritten.iter() {
+        screen.set(x, y, pixel);
+    }
+}
+
+impl Journal {
+    pub fn new() -> Self {
+        Journal::default()
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    pub fn record(
+        &mut self,
+        screen: &mut Screen,
+        instruction: &str,
+    ) -> Result<(), InstructionError> {
+        let instruction = parse_instruction(instruction)?;
+        let area = area(screen, instruction);
+        let before: Vec<Pixel> = area.iter().map(|&(x, y)| screen.get(x, y)).collect();
+        execute(screen, instruction)?;
+
+        // whatever the inverse gets wrong is exactly what has to be journaled; the inverse is run
+        // on the screen itself and redone, which only happens for shifts and costs one line
+        let inverse = inverse(screen, instruction).filter(|_| !area.is_empty());
+        if let Some(inverse) = inverse {
+            execute(screen, inverse).expect("inverse of a valid instruction is valid");
+        }
+        let overwritten = area
+            .into_iter()
+            .zip(before)
+            .filter(|&((x, y), pixel)| screen.get(x, y) != pixel)
+            .map(|((x, y), pixel)| (x, y, pixel))
+            .collect();
+        if inverse.is_some() {
+            execute(screen, instruction).expect("the instruction ran before");
+        }
+
+        self.entries.push(Entry {
+            instruction,
+            overwritten,
+        });
+        Ok(())
+    }
+
+    // step back over the last instruction, returning it
+    pub fn undo(&mut self, screen: &mut Screen) -> Option<Instruction> {
+        let entry = self.entries.pop()?;
+        undo_entry(screen, &entry);
+        Some(entry.instruction)
+    }
+
+    // run the program in reverse from its final image, returning every screen state in
+    // program order, starting with the one before the first instruction
+    pub fn rewind(&self, last: &Screen) -> Vec<Screen> {
+        let mut screen = last.clone();
+        let mut states = vec![screen.clone()];
+        for entry in self.entries.iter().rev() {
+            undo_entry(&mut screen, entry);
+            states.push(screen.clone());
+        }
+        states.reverse();
+        states
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROGRAM: &str = "rect 3x2\n\
+                           rotate column x=1 by 1\n\
+                           rotate row y=0 by -4\n\
+                           invert\n\
+                           shift row y=1 by 2\n\
+                           rect-off 2x3\n\
+                           flip column x=6\n\
+                           shift column x=4 up by 9\n\
+                           rotate row y=2 left by 11\n\
+                           clear\n\
+                           rect 1x1";
+
+    fn screens() -> Vec<Screen> {
+        let mut screen = Screen::new(7, 3);
+        let mut screens = vec![screen.clone()];
+        for line in PROGRAM.lines() {
+            crate::apply_instruction(&mut screen, line).unwrap();
+            screens.push(screen.clone());
+        }
+        screens
+    }
+
+    fn journal() -> (Journal, Screen) {
+        let mut screen = Screen::new(7, 3);
+        let mut journal = Journal::new();
+        for line in PROGRAM.lines() {
+            journal.record(&mut screen, line).unwrap();
+        }
+        (journal, screen)
+    }
+
+    #[test]
+    fn test_undo() {
+        let expected = screens();
+        let (mut journal, mut screen) = journal();
+        assert_eq!(expected[expected.len() - 1], screen);
+
+        for state in expected.iter().rev().skip(1) {
+            assert!(journal.undo(&mut screen).is_some());
+            assert_eq!(*state, screen);
+        }
+        assert_eq!(None, journal.undo(&mut screen));
+    }
+
+    #[test]
+    fn test_rewind() {
+        let (journal, screen) = journal();
+        assert_eq!(screens(), journal.rewind(&screen));
+        assert_eq!(PROGRAM.lines().count(), journal.entries().len());
+    }
+
+    #[test]
+    fn test_journal_keeps_only_lost_pixels() {
+        let (journal, _) = journal();
+        let overwritten: Vec<usize> = journal
+            .entries()
+            .iter()
+            .map(|entry| entry.overwritten.len())
+            .collect();
+        // rect 3x2 lights six pixels, rotations and flips need nothing
+        assert_eq!(6, overwritten[0]);
+        assert_eq!(0, overwritten[1]);
+        assert_eq!(0, overwritten[2]);
+        assert_eq!(0, overwritten[3]);
+        assert_eq!(0, overwritten[6]);
+
+        // a shift only journals what it pushed off the screen
+        let entries = journal.entries();
+        assert!(entries[4]
+            .overwritten
+            .iter()
+            .all(|&(x, y, _)| y == 1 && x >= 5));
+        assert!(entries[7].overwritten.iter().all(|&(x, _, _)| x == 4));
+        assert_eq!(
+            (2, 2),
+            (entries[4].overwritten.len(), entries[7].overwritten.len())
+        );
+    }
+
+    #[test]
+    fn test_record_rejects_invalid_instruction() {
+        let mut screen = Screen::new(7, 3);
+        let mut journal = Journal::new();
+        assert!(journal.record(&mut screen, "rotate row y=3 by 1").is_err());
+        assert!(journal.entries().is_empty());
+    }
+}
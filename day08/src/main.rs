@@ -1,94 +1,551 @@
 #[macro_use]
 extern crate lazy_static;
 use regex::Regex;
+use std::env;
+use std::fmt;
 use std::fs;
+use std::io::{self, BufWriter};
+
+mod image;
+mod journal;
+#[cfg(test)]
+mod lcg;
+mod ocr;
+mod recording;
+mod screen;
+mod synthesis;
+
+use image::{Color, Style};
+use journal::Journal;
+use recording::Recording;
+use screen::{Pixel, Screen};
+use std::time::Duration;
 
-type Screen = Vec<Vec<Pixel>>;
 const SCREEN_WIDTH: usize = 50;
 const SCREEN_HEIGHT: usize = 6;
 
-#[derive(Copy, Clone, PartialEq)]
-enum Pixel {
-    On,
-    Off,
+#[derive(Debug, PartialEq)]
+enum InstructionError {
+    Unrecognized(String),
+    RectOutOfRange { width: usize, height: usize },
+    RowOutOfRange(usize),
+    ColumnOutOfRange(usize),
 }
 
-fn part_1(screen: &Screen) -> usize {
-    screen.iter().flatten().filter(|p| **p == Pixel::On).count()
+impl fmt::Display for InstructionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InstructionError::Unrecognized(line) => {
+                write!(f, "unrecognized instruction '{}'", line)
+            }
+            InstructionError::RectOutOfRange { width, height } => {
+                write!(f, "rect {}x{} does not fit on the screen", width, height)
+            }
+            InstructionError::RowOutOfRange(row) => write!(f, "row {} is off the screen", row),
+            InstructionError::ColumnOutOfRange(column) => {
+                write!(f, "column {} is off the screen", column)
+            }
+        }
+    }
 }
 
-fn part_2(screen: &Screen) {
-    (0..SCREEN_HEIGHT).for_each(|y| {
-        (0..SCREEN_WIDTH).for_each(|x| match screen[y][x] {
-            Pixel::On => print!("#"),
-            Pixel::Off => print!("."),
-        });
-        println!();
-    });
+fn part_1(screen: &Screen) -> usize {
+    screen.count_on()
 }
 
-fn rect(screen: &mut Screen, width: usize, height: usize) {
-    (0..height).for_each(|y| {
-        (0..width).for_each(|x| {
-            screen[y][x] = Pixel::On;
-        })
-    });
+fn part_2(screen: &Screen) -> String {
+    ocr::read(screen).unwrap_or_else(|unknown| {
+        unknown.iter().for_each(|glyph| eprint!("{}", glyph));
+        panic!("unknown glyphs on the screen");
+    })
 }
 
-fn rotate_row(screen: &mut Screen, row: usize, count: usize) {
-    let current_row = screen[row].clone();
-    (0..SCREEN_WIDTH).for_each(|w| {
-        let new_position = (w + count) % SCREEN_WIDTH;
-        screen[row][new_position] = current_row[w];
-    });
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Instruction {
+    Rect { width: usize, height: usize },
+    RectOff { width: usize, height: usize },
+    Clear,
+    Invert,
+    RotateRow { row: usize, count: isize },
+    RotateColumn { column: usize, count: isize },
+    FlipRow(usize),
+    FlipColumn(usize),
+    ShiftRow { row: usize, count: isize },
+    ShiftColumn { column: usize, count: isize },
 }
 
-fn rotate_column(screen: &mut Screen, column: usize, count: usize) {
-    let current_column = (0..SCREEN_HEIGHT)
-        .map(|y| screen[y][column])
-        .collect::<Vec<Pixel>>();
-    (0..SCREEN_HEIGHT).for_each(|h| {
-        let new_position = (h + count) % SCREEN_HEIGHT;
-        screen[new_position][column] = current_column[h];
-    });
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Instruction::Rect { width, height } => write!(f, "rect {}x{}", width, height),
+            Instruction::RectOff { width, height } => write!(f, "rect-off {}x{}", width, height),
+            Instruction::Clear => write!(f, "clear"),
+            Instruction::Invert => write!(f, "invert"),
+            Instruction::RotateRow { row, count } => write!(f, "rotate row y={} by {}", row, count),
+            Instruction::RotateColumn { column, count } => {
+                write!(f, "rotate column x={} by {}", column, count)
+            }
+            Instruction::FlipRow(row) => write!(f, "flip row y={}", row),
+            Instruction::FlipColumn(column) => write!(f, "flip column x={}", column),
+            Instruction::ShiftRow { row, count } => write!(f, "shift row y={} by {}", row, count),
+            Instruction::ShiftColumn { column, count } => {
+                write!(f, "shift column x={} by {}", column, count)
+            }
+        }
+    }
 }
 
-fn apply_instruction(screen: &mut Screen, instruction: &str) {
+fn parse_instruction(instruction: &str) -> Result<Instruction, InstructionError> {
     lazy_static! {
         static ref RECT_RE: Regex = Regex::new(r"^rect (?P<width>\d+)x(?P<height>\d+)$").unwrap();
-        static ref ROTATE_ROW_RE: Regex =
-            Regex::new(r"^rotate row y=(?P<row>\d+) by (?P<count>\d+)$").unwrap();
-        static ref ROTATE_COLUMN_RE: Regex =
-            Regex::new(r"^rotate column x=(?P<column>\d+) by (?P<count>\d+)$").unwrap();
-    }
-
-    if RECT_RE.is_match(instruction) {
-        let caps = RECT_RE.captures(instruction).unwrap();
-        let width = caps["width"].parse::<usize>().unwrap();
-        let height = caps["height"].parse::<usize>().unwrap();
-        rect(screen, width, height);
-    } else if ROTATE_ROW_RE.is_match(instruction) {
-        let caps = ROTATE_ROW_RE.captures(instruction).unwrap();
-        let row = caps["row"].parse::<usize>().unwrap();
-        let count = caps["count"].parse::<usize>().unwrap();
-        rotate_row(screen, row, count);
-    } else if ROTATE_COLUMN_RE.is_match(instruction) {
-        let caps = ROTATE_COLUMN_RE.captures(instruction).unwrap();
-        let column = caps["column"].parse::<usize>().unwrap();
-        let count = caps["count"].parse::<usize>().unwrap();
-        rotate_column(screen, column, count);
+        static ref RECT_OFF_RE: Regex =
+            Regex::new(r"^rect-off (?P<width>\d+)x(?P<height>\d+)$").unwrap();
+        static ref CLEAR_RE: Regex = Regex::new(r"^clear$").unwrap();
+        static ref INVERT_RE: Regex = Regex::new(r"^invert$").unwrap();
+        static ref ROTATE_ROW_RE: Regex = Regex::new(
+            r"^rotate row y=(?P<row>\d+) (?:(?P<direction>left|right) )?by (?P<count>-?\d+)$"
+        )
+        .unwrap();
+        static ref ROTATE_COLUMN_RE: Regex = Regex::new(
+            r"^rotate column x=(?P<column>\d+) (?:(?P<direction>up|down) )?by (?P<count>-?\d+)$"
+        )
+        .unwrap();
+        static ref FLIP_ROW_RE: Regex = Regex::new(r"^flip row y=(?P<row>\d+)$").unwrap();
+        static ref FLIP_COLUMN_RE: Regex = Regex::new(r"^flip column x=(?P<column>\d+)$").unwrap();
+        static ref SHIFT_ROW_RE: Regex = Regex::new(
+            r"^shift row y=(?P<row>\d+) (?:(?P<direction>left|right) )?by (?P<count>-?\d+)$"
+        )
+        .unwrap();
+        static ref SHIFT_COLUMN_RE: Regex = Regex::new(
+            r"^shift column x=(?P<column>\d+) (?:(?P<direction>up|down) )?by (?P<count>-?\d+)$"
+        )
+        .unwrap();
+    }
+
+    let unrecognized = || InstructionError::Unrecognized(instruction.to_string());
+    let number = |caps: &regex::Captures, name: &str| {
+        caps[name].parse::<usize>().map_err(|_| unrecognized())
+    };
+    // left and up move pixels towards index 0, which is the negative direction
+    let count = |caps: &regex::Captures| {
+        let count = caps["count"].parse::<isize>().map_err(|_| unrecognized())?;
+        match caps.name("direction").map(|m| m.as_str()) {
+            Some("left") | Some("up") => count.checked_neg().ok_or_else(unrecognized),
+            _ => Ok(count),
+        }
+    };
+
+    if let Some(caps) = RECT_RE.captures(instruction) {
+        Ok(Instruction::Rect {
+            width: number(&caps, "width")?,
+            height: number(&caps, "height")?,
+        })
+    } else if let Some(caps) = RECT_OFF_RE.captures(instruction) {
+        Ok(Instruction::RectOff {
+            width: number(&caps, "width")?,
+            height: number(&caps, "height")?,
+        })
+    } else if CLEAR_RE.is_match(instruction) {
+        Ok(Instruction::Clear)
+    } else if INVERT_RE.is_match(instruction) {
+        Ok(Instruction::Invert)
+    } else if let Some(caps) = ROTATE_ROW_RE.captures(instruction) {
+        Ok(Instruction::RotateRow {
+            row: number(&caps, "row")?,
+            count: count(&caps)?,
+        })
+    } else if let Some(caps) = ROTATE_COLUMN_RE.captures(instruction) {
+        Ok(Instruction::RotateColumn {
+            column: number(&caps, "column")?,
+            count: count(&caps)?,
+        })
+    } else if let Some(caps) = FLIP_ROW_RE.captures(instruction) {
+        Ok(Instruction::FlipRow(number(&caps, "row")?))
+    } else if let Some(caps) = FLIP_COLUMN_RE.captures(instruction) {
+        Ok(Instruction::FlipColumn(number(&caps, "column")?))
+    } else if let Some(caps) = SHIFT_ROW_RE.captures(instruction) {
+        Ok(Instruction::ShiftRow {
+            row: number(&caps, "row")?,
+            count: count(&caps)?,
+        })
+    } else if let Some(caps) = SHIFT_COLUMN_RE.captures(instruction) {
+        Ok(Instruction::ShiftColumn {
+            column: number(&caps, "column")?,
+            count: count(&caps)?,
+        })
+    } else {
+        Err(unrecognized())
     }
 }
 
+fn execute(screen: &mut Screen, instruction: Instruction) -> Result<(), InstructionError> {
+    let check_rect = |width: usize, height: usize| {
+        if width > screen.width() || height > screen.height() {
+            Err(InstructionError::RectOutOfRange { width, height })
+        } else {
+            Ok(())
+        }
+    };
+    // a screen with no columns has no pixels in any row, and the other way round
+    let check_row = |row: usize| {
+        if row >= screen.height() || screen.width() == 0 {
+            Err(InstructionError::RowOutOfRange(row))
+        } else {
+            Ok(())
+        }
+    };
+    let check_column = |column: usize| {
+        if column >= screen.width() || screen.height() == 0 {
+            Err(InstructionError::ColumnOutOfRange(column))
+        } else {
+            Ok(())
+        }
+    };
+
+    match instruction {
+        Instruction::Rect { width, height } => {
+            check_rect(width, height)?;
+            screen.fill(width, height, Pixel::On);
+        }
+        Instruction::RectOff { width, height } => {
+            check_rect(width, height)?;
+            screen.fill(width, height, Pixel::Off);
+        }
+        Instruction::Clear => screen.fill(screen.width(), screen.height(), Pixel::Off),
+        Instruction::Invert => screen.invert(),
+        Instruction::RotateRow { row, count } => {
+            check_row(row)?;
+            let count = count.rem_euclid(screen.width() as isize) as usize;
+            screen.rotate_row(row, count);
+        }
+        Instruction::RotateColumn { column, count } => {
+            check_column(column)?;
+            let count = count.rem_euclid(screen.height() as isize) as usize;
+            screen.rotate_column(column, count);
+        }
+        Instruction::FlipRow(row) => {
+            check_row(row)?;
+            screen.flip_row(row);
+        }
+        Instruction::FlipColumn(column) => {
+            check_column(column)?;
+            screen.flip_column(column);
+        }
+        Instruction::ShiftRow { row, count } => {
+            check_row(row)?;
+            screen.shift_row(row, count);
+        }
+        Instruction::ShiftColumn { column, count } => {
+            check_column(column)?;
+            screen.shift_column(column, count);
+        }
+    }
+
+    Ok(())
+}
+
+fn apply_instruction(screen: &mut Screen, instruction: &str) -> Result<(), InstructionError> {
+    execute(screen, parse_instruction(instruction)?)
+}
+
 fn main() {
     let input = fs::read_to_string("input").expect("file not found");
     let input = input.trim();
 
-    let mut screen = vec![vec![Pixel::Off; SCREEN_WIDTH]; SCREEN_HEIGHT];
-    input
-        .lines()
-        .for_each(|line| apply_instruction(&mut screen, line));
+    let mut screen = Screen::new(SCREEN_WIDTH, SCREEN_HEIGHT);
+    let mut recording = Recording::new(&screen);
+    for (number, line) in input.lines().enumerate() {
+        if let Err(error) = recording.apply(&mut screen, line) {
+            panic!("line {}: {}", number + 1, error);
+        }
+    }
 
     assert_eq!(121, part_1(&screen));
-    part_2(&screen);
+    assert_eq!("RURUCEOEIL", part_2(&screen));
+
+    // `cargo run -- replay [delay in ms]` steps through the program in the terminal,
+    // `cargo run -- export` writes the screen as screen.pbm, .pgm, .png and .svg and the
+    // program as screen.apng, `cargo run -- synthesize` prints a shorter program that draws
+    // the same screen, `cargo run -- rewind` prints every screen from the last back to the
+    // first and `cargo run -- undo [count]` the screen `count` instructions before the end
+    let mut args = env::args().skip(1);
+    match args.next().as_deref() {
+        Some("export") => {
+            export(&screen, &recording).expect("failed to export screen");
+        }
+        Some("replay") => {
+            let delay = args.next().and_then(|ms| ms.parse().ok()).unwrap_or(100);
+            recording::replay(
+                &recording,
+                Duration::from_millis(delay),
+                false,
+                &recording::stdin_commands(),
+                &mut io::stdout(),
+            )
+            .expect("failed to replay");
+        }
+        Some("synthesize") => {
+            let program = synthesis::synthesize(&screen);
+            for instruction in &program {
+                println!("{}", instruction);
+            }
+            let drawn = synthesis::replay(SCREEN_WIDTH, SCREEN_HEIGHT, &program)
+                .expect("synthesized instructions fit on the screen");
+            print!("\n{}", drawn);
+        }
+        Some("rewind") => {
+            let journal = journal(input);
+            let states = journal.rewind(&screen);
+            print!("{}", screen);
+            for (entry, state) in journal.entries().iter().zip(&states).rev() {
+                print!("\nundo {}\n{}", entry.instruction, state);
+            }
+        }
+        Some("undo") => {
+            let count = args.next().and_then(|n| n.parse().ok()).unwrap_or(1);
+            let mut journal = journal(input);
+            let mut undone = screen.clone();
+            for _ in 0..count {
+                if journal.undo(&mut undone).is_none() {
+                    break;
+                }
+            }
+            print!("{}", undone);
+        }
+        _ => {}
+    }
+}
+
+fn journal(program: &str) -> Journal {
+    let mut journal = Journal::new();
+    let mut screen = Screen::new(SCREEN_WIDTH, SCREEN_HEIGHT);
+    for (number, line) in program.lines().enumerate() {
+        if let Err(error) = journal.record(&mut screen, line) {
+            panic!("line {}: {}", number + 1, error);
+        }
+    }
+    journal
+}
+
+fn export(screen: &Screen, recording: &Recording) -> io::Result<()> {
+    let style = &Style {
+        scale: 8,
+        on: Color(255, 204, 0),
+        off: Color(15, 15, 35),
+    };
+    image::write_pbm(
+        screen,
+        style,
+        &mut BufWriter::new(fs::File::create("screen.pbm")?),
+    )?;
+    image::write_pgm(
+        screen,
+        style,
+        &mut BufWriter::new(fs::File::create("screen.pgm")?),
+    )?;
+    image::write_png(
+        screen,
+        style,
+        &mut BufWriter::new(fs::File::create("screen.png")?),
+    )?;
+    image::write_svg(
+        screen,
+        style,
+        &mut BufWriter::new(fs::File::create("screen.svg")?),
+    )?;
+    image::write_apng(
+        &recording.screens(),
+        style,
+        Duration::from_millis(40),
+        &mut BufWriter::new(fs::File::create("screen.apng")?),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(screen: &mut Screen, instructions: &str) -> Result<(), InstructionError> {
+        instructions
+            .lines()
+            .try_for_each(|line| apply_instruction(screen, line))
+    }
+
+    #[test]
+    fn test_apply_instruction() {
+        let mut screen = Screen::new(7, 3);
+        run(
+            &mut screen,
+            "rect 3x2\n\
+             rotate column x=1 by 1\n\
+             rotate row y=0 by 4\n\
+             rotate column x=1 by 1",
+        )
+        .unwrap();
+        assert_eq!(".#..#.#\n#.#....\n.#.....\n", screen.to_string());
+        assert_eq!(6, part_1(&screen));
+    }
+
+    #[test]
+    fn test_apply_instruction_errors() {
+        let mut screen = Screen::new(50, 6);
+        assert_eq!(
+            Err(InstructionError::RectOutOfRange {
+                width: 60,
+                height: 3
+            }),
+            apply_instruction(&mut screen, "rect 60x3")
+        );
+        assert_eq!(
+            Err(InstructionError::RowOutOfRange(9)),
+            apply_instruction(&mut screen, "rotate row y=9 by 2")
+        );
+        assert_eq!(
+            Err(InstructionError::ColumnOutOfRange(50)),
+            apply_instruction(&mut screen, "rotate column x=50 by 2")
+        );
+        assert_eq!(
+            Err(InstructionError::Unrecognized(String::from(
+                "rotate diagonal"
+            ))),
+            apply_instruction(&mut screen, "rotate diagonal")
+        );
+        assert_eq!(
+            Err(InstructionError::Unrecognized(String::from(
+                "rect 99999999999999999999x1"
+            ))),
+            apply_instruction(&mut screen, "rect 99999999999999999999x1")
+        );
+        assert_eq!(Screen::new(50, 6), screen);
+    }
+
+    #[test]
+    fn test_empty_screen() {
+        let mut screen = Screen::new(0, 3);
+        assert_eq!(
+            Err(InstructionError::RowOutOfRange(1)),
+            apply_instruction(&mut screen, "rotate row y=1 by 2")
+        );
+        assert_eq!(
+            Err(InstructionError::RowOutOfRange(0)),
+            apply_instruction(&mut screen, "flip row y=0")
+        );
+        assert_eq!(Ok(()), apply_instruction(&mut screen, "rect 0x3"));
+
+        let mut screen = Screen::new(4, 0);
+        assert_eq!(
+            Err(InstructionError::ColumnOutOfRange(2)),
+            apply_instruction(&mut screen, "rotate column x=2 by 1")
+        );
+        assert_eq!(
+            Err(InstructionError::ColumnOutOfRange(0)),
+            apply_instruction(&mut screen, "shift column x=0 by 1")
+        );
+        assert_eq!(Ok(()), apply_instruction(&mut screen, "invert"));
+        assert_eq!(0, part_1(&screen));
+    }
+
+    #[test]
+    fn test_reverse_rotations() {
+        let expected = {
+            let mut screen = Screen::new(7, 3);
+            run(
+                &mut screen,
+                "rect 2x2\nrotate row y=0 by 5\nrotate column x=1 by 2",
+            )
+            .unwrap();
+            screen
+        };
+        for instructions in &[
+            "rect 2x2\nrotate row y=0 by -2\nrotate column x=1 by -1",
+            "rect 2x2\nrotate row y=0 left by 2\nrotate column x=1 up by 1",
+            "rect 2x2\nrotate row y=0 right by 12\nrotate column x=1 down by 5",
+            "rect 2x2\nrotate row y=0 left by -5\nrotate column x=1 up by -2",
+        ] {
+            let mut screen = Screen::new(7, 3);
+            run(&mut screen, instructions).unwrap();
+            assert_eq!(expected, screen, "{}", instructions);
+        }
+    }
+
+    #[test]
+    fn test_rect_off_clear_invert() {
+        let mut screen = Screen::new(4, 3);
+        run(&mut screen, "rect 3x3\nrect-off 2x1\ninvert").unwrap();
+        assert_eq!("##.#\n...#\n...#\n", screen.to_string());
+        run(&mut screen, "clear").unwrap();
+        assert_eq!(Screen::new(4, 3), screen);
+    }
+
+    #[test]
+    fn test_flip() {
+        let mut screen = Screen::new(4, 3);
+        run(&mut screen, "rect 3x1\nflip row y=0\nflip column x=1").unwrap();
+        assert_eq!("..##\n....\n.#..\n", screen.to_string());
+    }
+
+    #[test]
+    fn test_shift() {
+        let mut screen = Screen::new(5, 3);
+        run(
+            &mut screen,
+            "rect 3x2\nshift row y=0 by 3\nshift row y=1 left by 1",
+        )
+        .unwrap();
+        assert_eq!("...##\n##...\n.....\n", screen.to_string());
+        run(
+            &mut screen,
+            "shift column x=4 down by 2\nshift column x=0 by -1",
+        )
+        .unwrap();
+        assert_eq!("#..#.\n.#...\n....#\n", screen.to_string());
+        run(&mut screen, "shift row y=0 by 99").unwrap();
+        assert_eq!(".....\n.#...\n....#\n", screen.to_string());
+    }
+
+    #[test]
+    fn test_parse_instruction() {
+        assert_eq!(
+            Ok(Instruction::RotateColumn {
+                column: 3,
+                count: -4
+            }),
+            parse_instruction("rotate column x=3 up by 4")
+        );
+        assert_eq!(
+            Ok(Instruction::FlipColumn(7)),
+            parse_instruction("flip column x=7")
+        );
+        assert!(parse_instruction("rotate row y=1 up by 4").is_err());
+        assert!(parse_instruction("flip row y=-1").is_err());
+        assert!(parse_instruction("invert 3x3").is_err());
+    }
+
+    #[test]
+    fn test_instruction_display() {
+        for line in &[
+            "rect 3x2",
+            "rect-off 1x1",
+            "clear",
+            "invert",
+            "rotate row y=1 by -3",
+            "rotate column x=4 by 2",
+            "flip row y=0",
+            "flip column x=2",
+            "shift row y=2 by 5",
+            "shift column x=0 by -1",
+        ] {
+            assert_eq!(*line, parse_instruction(line).unwrap().to_string());
+        }
+    }
+
+    #[test]
+    fn test_screen_from_str() {
+        let screen: Screen = "#..\n.##\n".parse().unwrap();
+        assert_eq!(3, screen.width());
+        assert_eq!(2, screen.height());
+        assert_eq!(Pixel::On, screen.get(2, 1));
+        assert!("#..\n.#\n".parse::<Screen>().is_err());
+        assert!("#x.\n".parse::<Screen>().is_err());
+    }
 }
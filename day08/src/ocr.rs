@@ -0,0 +1,126 @@
+use crate::screen::{Pixel, Screen};
+use std::fmt;
+
+pub const GLYPH_WIDTH: usize = 5;
+pub const GLYPH_HEIGHT: usize = 6;
+
+// the rightmost column of every cell is blank spacing and is left out of the table
+const GLYPHS: &[(char, [&str; GLYPH_HEIGHT])] = &[
+    ('A', [".##.", "#..#", "#..#", "####", "#..#", "#..#"]),
+    ('B', ["###.", "#..#", "###.", "#..#", "#..#", "###."]),
+    ('C', [".##.", "#..#", "#...", "#...", "#..#", ".##."]),
+    ('E', ["####", "#...", "###.", "#...", "#...", "####"]),
+    ('F', ["####", "#...", "###.", "#...", "#...", "#..."]),
+    ('G', [".##.", "#..#", "#...", "#.##", "#..#", ".###"]),
+    ('H', ["#..#", "#..#", "####", "#..#", "#..#", "#..#"]),
+    ('I', [".###", "..#.", "..#.", "..#.", "..#.", ".###"]),
+    ('J', ["..##", "...#", "...#", "...#", "#..#", ".##."]),
+    ('K', ["#..#", "#.#.", "##..", "#.#.", "#.#.", "#..#"]),
+    ('L', ["#...", "#...", "#...", "#...", "#...", "####"]),
+    ('O', [".##.", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('P', ["###.", "#..#", "#..#", "###.", "#...", "#..."]),
+    ('R', ["###.", "#..#", "#..#", "###.", "#.#.", "#..#"]),
+    ('S', [".###", "#...", "#...", ".##.", "...#", "###."]),
+    ('U', ["#..#", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('Z', ["####", "...#", "..#.", ".#..", "#...", "####"]),
+    (' ', ["....", "....", "....", "....", "....", "...."]),
+];
+
+#[derive(Debug, PartialEq)]
+pub struct UnknownGlyph {
+    pub column: usize,
+    pub rows: Vec<String>,
+}
+
+impl fmt::Display for UnknownGlyph {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "unknown glyph at column {}:", self.column)?;
+        for row in self.rows.iter() {
+            writeln!(f, "{}", row)?;
+        }
+        Ok(())
+    }
+}
+
+fn cell(screen: &Screen, column: usize) -> Vec<String> {
+    screen
+        .rows()
+        .map(|row| {
+            (column..column + GLYPH_WIDTH)
+                .map(|x| match row.get(x) {
+                    Some(Pixel::On) => '#',
+                    _ => '.',
+                })
+                .collect()
+        })
+        .collect()
+}
+
+pub fn read(screen: &Screen) -> Result<String, Vec<UnknownGlyph>> {
+    let width = screen.width();
+    let mut text = String::new();
+    let mut unknown = Vec::new();
+
+    for column in (0..width).step_by(GLYPH_WIDTH) {
+        let rows = cell(screen, column);
+        let glyph = GLYPHS.iter().find(|(_, glyph)| {
+            rows.len() == GLYPH_HEIGHT
+                && rows
+                    .iter()
+                    .zip(glyph.iter())
+                    .all(|(row, glyph_row)| row.starts_with(glyph_row) && row.ends_with('.'))
+        });
+
+        match glyph {
+            Some((letter, _)) => text.push(*letter),
+            None => unknown.push(UnknownGlyph { column, rows }),
+        }
+    }
+
+    if unknown.is_empty() {
+        Ok(text.trim_end().to_string())
+    } else {
+        Err(unknown)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn screen(rows: &[&str]) -> Screen {
+        rows.join("\n").parse().unwrap()
+    }
+
+    #[test]
+    fn test_read() {
+        let screen = screen(&[
+            "#..#.####.#....#.....##..",
+            "#..#.#....#....#....#..#.",
+            "####.###..#....#....#..#.",
+            "#..#.#....#....#....#..#.",
+            "#..#.#....#....#....#..#.",
+            "#..#.####.####.####..##..",
+        ]);
+        assert_eq!(Ok(String::from("HELLO")), read(&screen));
+    }
+
+    #[test]
+    fn test_read_unknown() {
+        let screen = screen(&[
+            "#..#.#...#",
+            "#..#.##..#",
+            "####.#.#.#",
+            "#..#.#..##",
+            "#..#.#...#",
+            "#..#.#...#",
+        ]);
+        let unknown = read(&screen).unwrap_err();
+        assert_eq!(1, unknown.len());
+        assert_eq!(5, unknown[0].column);
+        assert_eq!("#...#", unknown[0].rows[0]);
+        assert!(unknown[0]
+            .to_string()
+            .starts_with("unknown glyph at column 5:\n#...#\n"));
+    }
+}
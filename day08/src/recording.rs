@@ -0,0 +1,250 @@
+use crate::screen::Screen;
+use crate::{apply_instruction, InstructionError};
+use std::io::{self, BufRead, Write};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+pub struct Frame {
+    pub instruction: Option<String>,
+    pub screen: Screen,
+}
+
+pub struct Recording {
+    frames: Vec<Frame>,
+}
+
+impl Recording {
+    pub fn new(screen: &Screen) -> Self {
+        Recording {
+            frames: vec![Frame {
+                instruction: None,
+                screen: screen.clone(),
+            }],
+        }
+    }
+
+    pub fn apply(
+        &mut self,
+        screen: &mut Screen,
+        instruction: &str,
+    ) -> Result<(), InstructionError> {
+        apply_instruction(screen, instruction)?;
+        self.frames.push(Frame {
+            instruction: Some(instruction.to_string()),
+            screen: screen.clone(),
+        });
+        Ok(())
+    }
+
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    pub fn screens(&self) -> Vec<&Screen> {
+        self.frames().iter().map(|frame| &frame.screen).collect()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Command {
+    TogglePause,
+    Step,
+    Back,
+    Quit,
+}
+
+impl Command {
+    fn parse(line: &str) -> Option<Self> {
+        match line.trim() {
+            "" | "n" => Some(Command::Step),
+            "b" => Some(Command::Back),
+            "p" => Some(Command::TogglePause),
+            "q" => Some(Command::Quit),
+            _ => None,
+        }
+    }
+}
+
+// stdin is read on its own thread so that a running replay can be paused at any time
+pub fn stdin_commands() -> Receiver<Command> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let command = match line.ok().as_deref().map(Command::parse) {
+                Some(Some(command)) => command,
+                Some(None) => continue,
+                None => break,
+            };
+            if sender.send(command).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+fn show<W: Write>(recording: &Recording, index: usize, writer: &mut W) -> io::Result<()> {
+    let frame = &recording.frames[index];
+    write!(writer, "\x1b[2J\x1b[H")?;
+    writeln!(
+        writer,
+        "frame {}/{}: {}",
+        index,
+        recording.frames.len() - 1,
+        frame.instruction.as_deref().unwrap_or("(blank screen)")
+    )?;
+    write!(writer, "{}", frame.screen)?;
+    writeln!(writer, "[enter/n] step  [b] back  [p] play/pause  [q] quit")?;
+    writer.flush()
+}
+
+pub fn replay<W: Write>(
+    recording: &Recording,
+    delay: Duration,
+    paused: bool,
+    commands: &Receiver<Command>,
+    writer: &mut W,
+) -> io::Result<()> {
+    let last = recording.frames.len() - 1;
+    let mut index = 0;
+    let mut paused = paused;
+    show(recording, index, writer)?;
+
+    loop {
+        let command = if paused || index == last {
+            match commands.recv() {
+                Ok(command) => command,
+                Err(_) => return Ok(()),
+            }
+        } else {
+            match commands.recv_timeout(delay) {
+                Ok(command) => command,
+                Err(RecvTimeoutError::Timeout) => Command::Step,
+                Err(RecvTimeoutError::Disconnected) => {
+                    thread::sleep(delay);
+                    Command::Step
+                }
+            }
+        };
+
+        match command {
+            Command::Step => index = (index + 1).min(last),
+            Command::Back => {
+                index = index.saturating_sub(1);
+                paused = true;
+            }
+            Command::TogglePause => paused = !paused,
+            Command::Quit => return Ok(()),
+        }
+        show(recording, index, writer)?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recording() -> Recording {
+        let mut screen = Screen::new(7, 3);
+        let mut recording = Recording::new(&screen);
+        for instruction in &["rect 3x2", "rotate column x=1 by 1", "rotate row y=0 by 4"] {
+            recording.apply(&mut screen, instruction).unwrap();
+        }
+        recording
+    }
+
+    #[test]
+    fn test_recording() {
+        let recording = recording();
+        assert_eq!(4, recording.frames().len());
+        assert_eq!(None, recording.frames()[0].instruction);
+        assert_eq!(Screen::new(7, 3), recording.frames()[0].screen);
+        assert_eq!(
+            Some(String::from("rotate column x=1 by 1")),
+            recording.frames()[2].instruction
+        );
+        assert_eq!(
+            "#.#....\n###....\n.#.....\n",
+            recording.frames()[2].screen.to_string()
+        );
+    }
+
+    #[test]
+    fn test_recording_rejects_invalid_instruction() {
+        let mut screen = Screen::new(7, 3);
+        let mut recording = Recording::new(&screen);
+        assert!(recording.apply(&mut screen, "rect 8x1").is_err());
+        assert_eq!(1, recording.frames().len());
+    }
+
+    #[test]
+    fn test_replay_stepping() {
+        let (sender, receiver) = mpsc::channel();
+        for command in &[
+            Command::Step,
+            Command::Step,
+            Command::Back,
+            Command::Step,
+            Command::Step,
+            Command::Step,
+            Command::Quit,
+        ] {
+            sender.send(*command).unwrap();
+        }
+
+        let mut output = Vec::new();
+        replay(
+            &recording(),
+            Duration::from_secs(60),
+            true,
+            &receiver,
+            &mut output,
+        )
+        .unwrap();
+        let headers: Vec<String> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .filter_map(|line| line.split(':').next())
+            .filter(|line| line.contains("frame "))
+            .map(|line| line.replace("\x1b[2J\x1b[H", ""))
+            .collect();
+        assert_eq!(
+            vec![
+                "frame 0/3",
+                "frame 1/3",
+                "frame 2/3",
+                "frame 1/3",
+                "frame 2/3",
+                "frame 3/3",
+                "frame 3/3"
+            ],
+            headers
+        );
+    }
+
+    #[test]
+    fn test_replay_playing() {
+        let (sender, receiver) = mpsc::channel();
+        drop(sender);
+
+        let mut output = Vec::new();
+        replay(
+            &recording(),
+            Duration::from_millis(1),
+            false,
+            &receiver,
+            &mut output,
+        )
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("frame 3/3: rotate row y=0 by 4"));
+    }
+
+    #[test]
+    fn test_command_parse() {
+        assert_eq!(Some(Command::Step), Command::parse(""));
+        assert_eq!(Some(Command::TogglePause), Command::parse("p\n"));
+        assert_eq!(None, Command::parse("x"));
+    }
+}
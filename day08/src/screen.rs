@@ -0,0 +1,490 @@
+use std::fmt;
+use std::str::FromStr;
+
+const WORD_BITS: usize = 64;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Pixel {
+    On,
+    Off,
+}
+
+// every row is packed into whole words, pixel x of a row being bit x % 64 of word x / 64;
+// the padding bits past the width are always zero
+#[derive(Clone, Debug, PartialEq)]
+pub struct Screen {
+    width: usize,
+    height: usize,
+    stride: usize,
+    words: Vec<u64>,
+}
+
+fn last_word_mask(length: usize) -> u64 {
+    match length % WORD_BITS {
+        0 => !0,
+        bits => (1 << bits) - 1,
+    }
+}
+
+// moves bit i of a `length` bit string to i + count, dropping whatever leaves the string
+fn shifted(bits: &[u64], length: usize, count: isize) -> Vec<u64> {
+    let mut result = vec![0; bits.len()];
+    if count.unsigned_abs() >= length {
+        return result;
+    }
+
+    let (words, offset) = (
+        count.unsigned_abs() / WORD_BITS,
+        count.unsigned_abs() % WORD_BITS,
+    );
+    let n = bits.len();
+    for (i, word) in result.iter_mut().enumerate() {
+        if count >= 0 {
+            if i >= words {
+                *word = bits[i - words] << offset;
+            }
+            if offset > 0 && i > words {
+                *word |= bits[i - words - 1] >> (WORD_BITS - offset);
+            }
+        } else {
+            if i + words < n {
+                *word = bits[i + words] >> offset;
+            }
+            if offset > 0 && i + words + 1 < n {
+                *word |= bits[i + words + 1] << (WORD_BITS - offset);
+            }
+        }
+    }
+    if let Some(last) = result.last_mut() {
+        *last &= last_word_mask(length);
+    }
+    result
+}
+
+fn low_mask(count: usize) -> u64 {
+    if count >= WORD_BITS {
+        !0
+    } else {
+        (1 << count) - 1
+    }
+}
+
+// the `count` <= 64 bits starting at bit `start`, which may straddle two words
+fn get_bits(words: &[u64], start: usize, count: usize) -> u64 {
+    let (index, offset) = (start / WORD_BITS, start % WORD_BITS);
+    let mut value = words[index] >> offset;
+    if offset > 0 && offset + count > WORD_BITS {
+        value |= words[index + 1] << (WORD_BITS - offset);
+    }
+    value & low_mask(count)
+}
+
+fn set_bits(words: &mut [u64], start: usize, count: usize, value: u64) {
+    let (index, offset) = (start / WORD_BITS, start % WORD_BITS);
+    let mask = low_mask(count);
+    let value = value & mask;
+    words[index] = words[index] & !(mask << offset) | value << offset;
+    if offset > 0 && offset + count > WORD_BITS {
+        let shift = WORD_BITS - offset;
+        words[index + 1] = words[index + 1] & !(mask >> shift) | value >> shift;
+    }
+}
+
+// reverses bits start..end in place, swapping up to a word from each end at a time
+fn reverse_bits(words: &mut [u64], mut start: usize, mut end: usize) {
+    while end > start + 1 {
+        let count = ((end - start) / 2).min(WORD_BITS);
+        let left = get_bits(words, start, count);
+        let right = get_bits(words, end - count, count);
+        let shift = WORD_BITS - count;
+        set_bits(words, start, count, right.reverse_bits() >> shift);
+        set_bits(words, end - count, count, left.reverse_bits() >> shift);
+        start += count;
+        end -= count;
+    }
+}
+
+// moves bit i of a `length` bit string to (i + count) % length, as three reversals
+fn rotate_bits(words: &mut [u64], length: usize, count: usize) {
+    let count = count % length.max(1);
+    if count == 0 {
+        return;
+    }
+    reverse_bits(words, 0, length);
+    reverse_bits(words, 0, count);
+    reverse_bits(words, count, length);
+}
+
+impl Screen {
+    pub fn new(width: usize, height: usize) -> Self {
+        let stride = width.div_ceil(WORD_BITS);
+        Screen {
+            width,
+            height,
+            stride,
+            words: vec![0; stride * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Pixel {
+        assert!(x < self.width && y < self.height);
+        if self.words[y * self.stride + x / WORD_BITS] >> (x % WORD_BITS) & 1 == 1 {
+            Pixel::On
+        } else {
+            Pixel::Off
+        }
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, pixel: Pixel) {
+        assert!(x < self.width && y < self.height);
+        let word = &mut self.words[y * self.stride + x / WORD_BITS];
+        let bit = 1 << (x % WORD_BITS);
+        match pixel {
+            Pixel::On => *word |= bit,
+            Pixel::Off => *word &= !bit,
+        }
+    }
+
+    pub fn row(&self, y: usize) -> Vec<Pixel> {
+        (0..self.width).map(|x| self.get(x, y)).collect()
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = Vec<Pixel>> + '_ {
+        (0..self.height).map(move |y| self.row(y))
+    }
+
+    pub fn count_on(&self) -> usize {
+        self.words
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum()
+    }
+
+    fn row_words(&self, y: usize) -> &[u64] {
+        &self.words[y * self.stride..(y + 1) * self.stride]
+    }
+
+    fn row_words_mut(&mut self, y: usize) -> &mut [u64] {
+        &mut self.words[y * self.stride..(y + 1) * self.stride]
+    }
+
+    fn set_row_words(&mut self, y: usize, bits: &[u64]) {
+        self.words[y * self.stride..(y + 1) * self.stride].copy_from_slice(bits);
+    }
+
+    // a column is gathered into a bit string of its own, transformed like a row and scattered back
+    fn column_words(&self, x: usize) -> Vec<u64> {
+        let (index, bit) = (x / WORD_BITS, x % WORD_BITS);
+        let mut bits = vec![0; self.height.div_ceil(WORD_BITS)];
+        let mut column = self.words[index..].iter().step_by(self.stride);
+        for word in bits.iter_mut() {
+            for (y, pixel) in column.by_ref().take(WORD_BITS).enumerate() {
+                *word |= (pixel >> bit & 1) << y;
+            }
+        }
+        bits
+    }
+
+    // only the rows where `old` and `new` differ are touched
+    fn set_column_words(&mut self, x: usize, old: &[u64], new: &[u64]) {
+        let (index, bit) = (x / WORD_BITS, x % WORD_BITS);
+        for (i, (old, new)) in old.iter().zip(new).enumerate() {
+            let mut changed = old ^ new;
+            while changed != 0 {
+                let y = i * WORD_BITS + changed.trailing_zeros() as usize;
+                self.words[y * self.stride + index] ^= 1 << bit;
+                changed &= changed - 1;
+            }
+        }
+    }
+
+    pub fn fill(&mut self, width: usize, height: usize, pixel: Pixel) {
+        assert!(width <= self.width && height <= self.height);
+        for y in 0..height {
+            let row = &mut self.words[y * self.stride..(y + 1) * self.stride];
+            for (i, word) in row.iter_mut().enumerate().take(width.div_ceil(WORD_BITS)) {
+                let mask = if (i + 1) * WORD_BITS <= width {
+                    !0
+                } else {
+                    last_word_mask(width)
+                };
+                match pixel {
+                    Pixel::On => *word |= mask,
+                    Pixel::Off => *word &= !mask,
+                }
+            }
+        }
+    }
+
+    pub fn invert(&mut self) {
+        let mask = last_word_mask(self.width);
+        for row in self.words.chunks_mut(self.stride.max(1)) {
+            row.iter_mut().for_each(|word| *word = !*word);
+            if let Some(last) = row.last_mut() {
+                *last &= mask;
+            }
+        }
+    }
+
+    pub fn rotate_row(&mut self, y: usize, count: usize) {
+        let width = self.width;
+        rotate_bits(self.row_words_mut(y), width, count);
+    }
+
+    pub fn rotate_column(&mut self, x: usize, count: usize) {
+        let count = count % self.height.max(1);
+        if count == 0 {
+            return;
+        }
+        let bits = self.column_words(x);
+        let mut rotated = bits.clone();
+        rotate_bits(&mut rotated, self.height, count);
+        self.set_column_words(x, &bits, &rotated);
+    }
+
+    pub fn flip_row(&mut self, y: usize) {
+        let width = self.width;
+        reverse_bits(self.row_words_mut(y), 0, width);
+    }
+
+    pub fn flip_column(&mut self, x: usize) {
+        let bits = self.column_words(x);
+        let mut reversed = bits.clone();
+        reverse_bits(&mut reversed, 0, self.height);
+        self.set_column_words(x, &bits, &reversed);
+    }
+
+    pub fn shift_row(&mut self, y: usize, count: isize) {
+        let bits = shifted(self.row_words(y), self.width, count);
+        self.set_row_words(y, &bits);
+    }
+
+    pub fn shift_column(&mut self, x: usize, count: isize) {
+        let bits = self.column_words(x);
+        self.set_column_words(x, &bits, &shifted(&bits, self.height, count));
+    }
+}
+
+impl fmt::Display for Screen {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for row in self.rows() {
+            for pixel in row {
+                match pixel {
+                    Pixel::On => write!(f, "#")?,
+                    Pixel::Off => write!(f, ".")?,
+                }
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Screen {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rows = s
+            .lines()
+            .map(|line| {
+                line.chars()
+                    .map(|c| match c {
+                        '#' => Ok(Pixel::On),
+                        '.' => Ok(Pixel::Off),
+                        _ => Err(format!("unexpected character '{}'", c)),
+                    })
+                    .collect::<Result<Vec<Pixel>, String>>()
+            })
+            .collect::<Result<Vec<Vec<Pixel>>, String>>()?;
+
+        let width = rows.first().map_or(0, |row| row.len());
+        if rows.iter().any(|row| row.len() != width) {
+            return Err(String::from("rows differ in length"));
+        }
+
+        let mut screen = Screen::new(width, rows.len());
+        for (y, row) in rows.iter().enumerate() {
+            for (x, pixel) in row.iter().enumerate() {
+                screen.set(x, y, *pixel);
+            }
+        }
+        Ok(screen)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lcg::Lcg;
+
+    // the straightforward one-enum-per-pixel model the packed screen has to agree with
+    struct Model {
+        rows: Vec<Vec<Pixel>>,
+    }
+
+    impl Model {
+        fn rotate_row(&mut self, y: usize, count: usize) {
+            let width = self.rows[y].len();
+            let row = self.rows[y].clone();
+            (0..width).for_each(|x| self.rows[y][(x + count) % width] = row[x]);
+        }
+
+        fn shift_row(&mut self, y: usize, count: isize) {
+            let width = self.rows[y].len() as isize;
+            let row = self.rows[y].clone();
+            (0..width).for_each(|x| {
+                let source = x - count;
+                self.rows[y][x as usize] = if source >= 0 && source < width {
+                    row[source as usize]
+                } else {
+                    Pixel::Off
+                };
+            });
+        }
+
+        fn transpose(&mut self) {
+            let height = self.rows.len();
+            let width = self.rows[0].len();
+            self.rows = (0..width)
+                .map(|x| (0..height).map(|y| self.rows[y][x]).collect())
+                .collect();
+        }
+
+        fn render(&self) -> String {
+            self.rows
+                .iter()
+                .map(|row| {
+                    let mut line: String = row
+                        .iter()
+                        .map(|pixel| if *pixel == Pixel::On { '#' } else { '.' })
+                        .collect();
+                    line.push('\n');
+                    line
+                })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn test_matches_model() {
+        let mut rng = Lcg(8);
+        for &(width, height) in &[(1, 1), (7, 3), (50, 6), (64, 64), (65, 3), (130, 129)] {
+            let mut screen = Screen::new(width, height);
+            let mut model = Model {
+                rows: vec![vec![Pixel::Off; width]; height],
+            };
+
+            for _ in 0..500 {
+                let (x, y) = (rng.next(width), rng.next(height));
+                let count = rng.next(3 * width.max(height)) as isize - width.max(height) as isize;
+                match rng.next(8) {
+                    0 => {
+                        let (w, h) = (rng.next(width + 1), rng.next(height + 1));
+                        let pixel = if rng.next(2) == 0 {
+                            Pixel::On
+                        } else {
+                            Pixel::Off
+                        };
+                        screen.fill(w, h, pixel);
+                        (0..h).for_each(|y| (0..w).for_each(|x| model.rows[y][x] = pixel));
+                    }
+                    1 => {
+                        screen.invert();
+                        model.rows.iter_mut().flatten().for_each(|pixel| {
+                            *pixel = if *pixel == Pixel::On {
+                                Pixel::Off
+                            } else {
+                                Pixel::On
+                            }
+                        });
+                    }
+                    2 => {
+                        let count = count.rem_euclid(width as isize) as usize;
+                        screen.rotate_row(y, count);
+                        model.rotate_row(y, count);
+                    }
+                    3 => {
+                        let count = count.rem_euclid(height as isize) as usize;
+                        screen.rotate_column(x, count);
+                        model.transpose();
+                        model.rotate_row(x, count);
+                        model.transpose();
+                    }
+                    4 => {
+                        screen.flip_row(y);
+                        model.rows[y].reverse();
+                    }
+                    5 => {
+                        screen.flip_column(x);
+                        model.transpose();
+                        model.rows[x].reverse();
+                        model.transpose();
+                    }
+                    6 => {
+                        screen.shift_row(y, count);
+                        model.shift_row(y, count);
+                    }
+                    _ => {
+                        screen.shift_column(x, count);
+                        model.transpose();
+                        model.shift_row(x, count);
+                        model.transpose();
+                    }
+                }
+                assert_eq!(model.render(), screen.to_string());
+            }
+
+            let lit = model.rows.iter().flatten().filter(|p| **p == Pixel::On);
+            assert_eq!(lit.count(), screen.count_on());
+        }
+    }
+
+    #[test]
+    fn test_large_screen() {
+        let mut screen = Screen::new(10_000, 10_000);
+        screen.fill(3, 2, Pixel::On);
+        for i in 0..1_000 {
+            screen.rotate_row(i % 2, 9_999);
+            screen.rotate_column(i % 3, 7_001);
+        }
+        assert_eq!(6, screen.count_on());
+        screen.invert();
+        assert_eq!(10_000 * 10_000 - 6, screen.count_on());
+    }
+
+    // random rects and rotations like the puzzle's, run with `cargo test --release -- --ignored`
+    #[test]
+    #[ignore]
+    fn test_stress() {
+        let size = 10_000;
+        let mut rng = Lcg(8);
+        let mut screen = Screen::new(size, size);
+        for _ in 0..1_000_000 {
+            match rng.next(3) {
+                0 => {
+                    let (width, height) = (1 + rng.next(64), 1 + rng.next(64));
+                    screen.fill(width, height, Pixel::On);
+                }
+                1 => {
+                    let (y, count) = (rng.next(size), rng.next(size));
+                    screen.rotate_row(y, count);
+                }
+                _ => {
+                    let (x, count) = (rng.next(size), rng.next(size));
+                    screen.rotate_column(x, count);
+                }
+            }
+        }
+        // rotations keep the count, so it only comes out right if they put every pixel back where
+        // the next fill expects it
+        assert_eq!(271_455, screen.count_on());
+    }
+}
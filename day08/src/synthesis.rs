@@ -0,0 +1,306 @@
+use crate::screen::{Pixel, Screen};
+use crate::{apply_instruction, Instruction, InstructionError};
+
+#[derive(Copy, Clone)]
+enum Move {
+    Row(usize, usize),
+    Column(usize, usize),
+}
+
+impl Move {
+    // the search runs backwards from the target, so every move is undone rather than applied
+    fn undo(self, screen: &mut Screen) {
+        match self {
+            Move::Row(row, count) => {
+                let width = screen.width();
+                screen.rotate_row(row, (width - count) % width);
+            }
+            Move::Column(column, count) => {
+                let height = screen.height();
+                screen.rotate_column(column, (height - count) % height);
+            }
+        }
+    }
+
+    fn instruction(self) -> Instruction {
+        match self {
+            Move::Row(row, count) => Instruction::RotateRow {
+                row,
+                count: count as isize,
+            },
+            Move::Column(column, count) => Instruction::RotateColumn {
+                column,
+                count: count as isize,
+            },
+        }
+    }
+}
+
+fn is_lit(screen: &Screen) -> bool {
+    screen.count_on() > 0
+}
+
+// the largest lit rectangle anchored at the top-left corner
+fn corner_rect(screen: &Screen) -> (usize, usize) {
+    let mut best = (0, 0);
+    let mut limit = screen.width();
+    for y in 0..screen.height() {
+        limit = screen
+            .row(y)
+            .iter()
+            .take(limit)
+            .take_while(|pixel| **pixel == Pixel::On)
+            .count();
+        if limit == 0 {
+            break;
+        }
+        if limit * (y + 1) > best.0 * best.1 {
+            best = (limit, y + 1);
+        }
+    }
+    best
+}
+
+fn corner_area(screen: &Screen) -> usize {
+    let (width, height) = corner_rect(screen);
+    width * height
+}
+
+// start of the longest cyclic run of lit pixels
+fn longest_run_start(pixels: &[Pixel]) -> Option<usize> {
+    let length = pixels.len();
+    let first_off = pixels.iter().position(|pixel| *pixel == Pixel::Off);
+    let first_off = match first_off {
+        Some(index) => index,
+        None => return if length > 0 { Some(0) } else { None },
+    };
+
+    let mut best: Option<(usize, usize)> = None;
+    let mut run = 0;
+    for i in 1..=length {
+        let index = (first_off + i) % length;
+        if pixels[index] == Pixel::On {
+            run += 1;
+        } else if run > 0 {
+            let start = (index + length - run) % length;
+            if best.is_none_or(|(_, longest)| run > longest) {
+                best = Some((start, run));
+            }
+            run = 0;
+        }
+    }
+    best.map(|(start, _)| start)
+}
+
+fn column(screen: &Screen, x: usize) -> Vec<Pixel> {
+    (0..screen.height()).map(|y| screen.get(x, y)).collect()
+}
+
+fn score(screen: &Screen, moves: &[Move]) -> usize {
+    let mut screen = screen.clone();
+    moves.iter().for_each(|m| m.undo(&mut screen));
+    corner_area(&screen)
+}
+
+// moves that light the top-left corner, best first
+fn best_corner_move(screen: &Screen) -> Option<(Move, usize)> {
+    let row_moves = (0..screen.width())
+        .filter(|x| screen.get(*x, 0) == Pixel::On)
+        .map(|x| Move::Row(0, x));
+    let column_moves = (0..screen.height())
+        .filter(|y| screen.get(0, *y) == Pixel::On)
+        .map(|y| Move::Column(0, y));
+
+    row_moves
+        .chain(column_moves)
+        .map(|m| (m, score(screen, &[m])))
+        .fold(None, |best, (m, area)| match best {
+            Some((_, best_area)) if best_area >= area => best,
+            _ => Some((m, area)),
+        })
+}
+
+fn best_gathering_move(screen: &Screen) -> Option<Move> {
+    let column_moves = (0..screen.width())
+        .filter_map(|x| longest_run_start(&column(screen, x)).map(|start| Move::Column(x, start)));
+    let row_moves = (0..screen.height())
+        .filter_map(|y| longest_run_start(&screen.row(y)).map(|start| Move::Row(y, start)));
+
+    column_moves
+        .chain(row_moves)
+        .map(|m| {
+            let mut gathered = screen.clone();
+            m.undo(&mut gathered);
+            let area = best_corner_move(&gathered).map_or(0, |(_, area)| area);
+            (m, area)
+        })
+        .fold(None, |best: Option<(Move, usize)>, (m, area)| match best {
+            Some((_, best_area)) if best_area >= area => best,
+            _ => Some((m, area)),
+        })
+        .map(|(m, _)| m)
+}
+
+// one move at a time, always towards the largest rect in the corner
+fn greedy(target: &Screen) -> Vec<Instruction> {
+    let mut screen = target.clone();
+    let mut program = Vec::new();
+
+    while is_lit(&screen) {
+        if screen.get(0, 0) == Pixel::On {
+            let (width, height) = corner_rect(&screen);
+            screen.fill(width, height, Pixel::Off);
+            program.push(Instruction::Rect { width, height });
+            continue;
+        }
+
+        let m = match best_corner_move(&screen) {
+            Some((m, _)) => m,
+            None => best_gathering_move(&screen).unwrap(),
+        };
+        m.undo(&mut screen);
+        program.push(m.instruction());
+    }
+
+    program.reverse();
+    program
+}
+
+// sweep the top row left to right, lifting one pixel of every column into a single run
+// that moves along with the sweep and is cleared with one rect per pass
+fn sweep(target: &Screen) -> Vec<Instruction> {
+    let mut screen = target.clone();
+    let mut program = Vec::new();
+    let width = screen.width();
+    let is_empty = |screen: &Screen, x: usize| column(screen, x).iter().all(|p| *p == Pixel::Off);
+
+    while is_lit(&screen) {
+        let mut x = 0;
+        let mut length = 0;
+        while x < width {
+            if screen.get(x, 0) == Pixel::On {
+                length += 1;
+                x += 1;
+            } else if let Some(start) = longest_run_start(&column(&screen, x)) {
+                let m = Move::Column(x, start);
+                m.undo(&mut screen);
+                program.push(m.instruction());
+                length += 1;
+                x += 1;
+            } else if length == 0 {
+                x += 1;
+            } else {
+                let gap = (x..width).take_while(|x| is_empty(&screen, *x)).count();
+                if x + gap == width {
+                    break;
+                }
+                let m = Move::Row(0, width - gap);
+                m.undo(&mut screen);
+                program.push(m.instruction());
+                x += gap;
+            }
+        }
+
+        // rows below that hold the same run are lined up too, so one rect clears them all
+        let start = x - length;
+        let mut height = 0;
+        for y in 0..screen.height() {
+            let aligned = (0..length).all(|i| screen.get((start + i) % width, y) == Pixel::On);
+            if !aligned || (y > 0 && start != 0 && length < 2) {
+                break;
+            }
+            if start != 0 {
+                let m = Move::Row(y, start);
+                m.undo(&mut screen);
+                program.push(m.instruction());
+            }
+            height += 1;
+        }
+        screen.fill(length, height, Pixel::Off);
+        program.push(Instruction::Rect {
+            width: length,
+            height,
+        });
+    }
+
+    program.reverse();
+    program
+}
+
+pub fn synthesize(target: &Screen) -> Vec<Instruction> {
+    let greedy = greedy(target);
+    let sweep = sweep(target);
+    if greedy.len() < sweep.len() {
+        greedy
+    } else {
+        sweep
+    }
+}
+
+pub fn replay(
+    width: usize,
+    height: usize,
+    program: &[Instruction],
+) -> Result<Screen, InstructionError> {
+    let mut screen = Screen::new(width, height);
+    for instruction in program {
+        apply_instruction(&mut screen, &instruction.to_string())?;
+    }
+    Ok(screen)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(target: &str) -> usize {
+        let target: Screen = target.parse().unwrap();
+        for program in &[greedy(&target), sweep(&target)] {
+            assert_eq!(
+                target,
+                replay(target.width(), target.height(), program).unwrap()
+            );
+        }
+        let program = synthesize(&target);
+        program.len()
+    }
+
+    #[test]
+    fn test_synthesize_blank() {
+        assert_eq!(0, check("....\n....\n"));
+    }
+
+    #[test]
+    fn test_synthesize_rect() {
+        assert_eq!(1, check("###.\n###.\n"));
+        assert_eq!(3, check(".###\n.###\n"));
+    }
+
+    #[test]
+    fn test_synthesize_letters() {
+        let target = "#..#.####.#....#.....##..\n\
+                      #..#.#....#....#....#..#.\n\
+                      ####.###..#....#....#..#.\n\
+                      #..#.#....#....#....#..#.\n\
+                      #..#.#....#....#....#..#.\n\
+                      #..#.####.####.####..##..\n";
+        let lit = target.matches('#').count();
+        assert!(check(target) < 2 * lit);
+    }
+
+    #[test]
+    fn test_synthesize_scattered() {
+        check("#.#.#\n.#.#.\n#.#.#\n");
+        check("....#\n.....\n..#..\n");
+        check("#####\n#####\n#####\n");
+    }
+
+    #[test]
+    fn test_longest_run_start() {
+        let pixels = |s: &str| -> Vec<Pixel> { s.parse::<Screen>().unwrap().row(0).to_vec() };
+        assert_eq!(None, longest_run_start(&pixels("....")));
+        assert_eq!(Some(0), longest_run_start(&pixels("####")));
+        assert_eq!(Some(3), longest_run_start(&pixels("#..##")));
+        assert_eq!(Some(4), longest_run_start(&pixels(".##.###")));
+    }
+}
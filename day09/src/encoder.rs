@@ -0,0 +1,420 @@
+use std::fmt;
+
+// longest repeating unit that is looked for
+const MAX_LENGTH: usize = 1 << 13;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Mode {
+    Greedy,
+    // shortest version 1 encoding whose repeating units are at most MAX_LENGTH bytes long
+    Optimal,
+    Nested,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum EncodeError {
+    // version 2 reads every '(' as the start of a marker, so there is no way to produce one
+    Unencodable { offset: usize },
+    // the decoder skips whitespace, so it would not survive a round trip
+    Whitespace { offset: usize },
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EncodeError::Unencodable { offset } => {
+                write!(f, "'(' at byte {} cannot be encoded in version 2", offset)
+            }
+            EncodeError::Whitespace { offset } => {
+                write!(f, "whitespace at byte {} cannot be encoded", offset)
+            }
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Step {
+    Literal,
+    Repeat { length: usize, repeats: usize },
+}
+
+fn digits(n: usize) -> usize {
+    std::iter::successors(Some(n), |n| Some(n / 10))
+        .take_while(|n| *n >= 10)
+        .count()
+        + 1
+}
+
+fn marker_cost(length: usize, repeats: usize) -> usize {
+    digits(length) + digits(repeats) + 3
+}
+
+// a bare '(' would start a marker, so in version 1 it has to be wrapped in one
+fn literal_cost(byte: u8) -> usize {
+    if byte == b'(' {
+        marker_cost(1, 1) + 1
+    } else {
+        1
+    }
+}
+
+fn repeats_at(input: &[u8], start: usize, length: usize) -> usize {
+    let pattern = &input[start..start + length];
+    1 + input[start + length..]
+        .chunks_exact(length)
+        .take_while(|chunk| *chunk == pattern)
+        .count()
+}
+
+// for every byte, the index of the next byte with the same value
+fn next_same(input: &[u8]) -> Vec<usize> {
+    let mut last = [input.len(); 256];
+    let mut next = vec![input.len(); input.len()];
+    for (i, byte) in input.iter().enumerate().rev() {
+        next[i] = last[*byte as usize];
+        last[*byte as usize] = i;
+    }
+    next
+}
+
+// a repeating unit has to be followed by its own first byte, so only those lengths are tried
+fn lengths(next: &[usize], start: usize) -> impl Iterator<Item = usize> + '_ {
+    let limit = start + MAX_LENGTH;
+    std::iter::successors(next.get(start).copied(), move |j| next.get(*j).copied())
+        .take_while(move |j| *j < next.len() && *j <= limit)
+        .map(move |j| j - start)
+}
+
+// the repeat at `start` that saves the most bytes right away, if any saves anything
+fn best_repeat(text: &str, next: &[usize], start: usize) -> Option<Step> {
+    let input = text.as_bytes();
+    if !text.is_char_boundary(start) {
+        return None;
+    }
+    lengths(next, start)
+        .filter(|length| start + 2 * length <= input.len())
+        .filter_map(|length| {
+            let repeats = repeats_at(input, start, length);
+            let saved =
+                (length * repeats) as isize - (length + marker_cost(length, repeats)) as isize;
+            if repeats > 1 && saved > 0 {
+                Some((saved, Step::Repeat { length, repeats }))
+            } else {
+                None
+            }
+        })
+        .max_by_key(|(saved, _)| *saved)
+        .map(|(_, step)| step)
+}
+
+fn greedy_steps(text: &str) -> Vec<Step> {
+    let next = next_same(text.as_bytes());
+    let mut steps = Vec::new();
+    let mut position = 0;
+    while position < text.len() {
+        let step = best_repeat(text, &next, position).unwrap_or(Step::Literal);
+        position += match step {
+            Step::Literal => 1,
+            Step::Repeat { length, repeats } => length * repeats,
+        };
+        steps.push(step);
+    }
+    steps
+}
+
+// shortest version 1 encoding of every suffix, built from the back
+fn optimal_steps(text: &str) -> Vec<Step> {
+    let input = text.as_bytes();
+    let n = input.len();
+    let next = next_same(input);
+    let mut cost = vec![0; n + 1];
+    let mut choice = vec![Step::Literal; n];
+    // how far input[i..] and input[i + length..] agree, valid while updated[length] == i
+    let mut extension = vec![0; MAX_LENGTH + 1];
+    let mut updated = vec![usize::MAX; MAX_LENGTH + 1];
+
+    for i in (0..n).rev() {
+        cost[i] = literal_cost(input[i]) + cost[i + 1];
+        choice[i] = Step::Literal;
+        let boundary = text.is_char_boundary(i);
+
+        for length in lengths(&next, i) {
+            extension[length] = if updated[length] == i + 1 {
+                extension[length] + 1
+            } else {
+                1
+            };
+            updated[length] = i;
+
+            if !boundary {
+                continue;
+            }
+            // the marker costs the same for every repeat count with the same number of digits,
+            // so each such band only needs its cheapest rest
+            let max = 1 + extension[length] / length;
+            let mut low = 2;
+            while low <= max {
+                let high = 10usize
+                    .checked_pow(digits(low) as u32)
+                    .map_or(max, |power| max.min(power - 1));
+                let (rest, repeats) = (low..=high)
+                    .map(|repeats| (cost[i + length * repeats], repeats))
+                    .min()
+                    .unwrap();
+                let candidate = marker_cost(length, repeats) + length + rest;
+                if candidate < cost[i] {
+                    cost[i] = candidate;
+                    choice[i] = Step::Repeat { length, repeats };
+                }
+                low = high + 1;
+            }
+
+            // a single repeat only pays off as a wrapper around a stretch from one '(' to another
+            if input[i] == b'(' && i + length < n {
+                let candidate = marker_cost(length + 1, 1) + length + 1 + cost[i + length + 1];
+                if candidate < cost[i] {
+                    cost[i] = candidate;
+                    choice[i] = Step::Repeat {
+                        length: length + 1,
+                        repeats: 1,
+                    };
+                }
+            }
+        }
+    }
+
+    let mut steps = Vec::new();
+    let mut position = 0;
+    while position < n {
+        let step = choice[position];
+        position += match step {
+            Step::Literal => 1,
+            Step::Repeat { length, repeats } => length * repeats,
+        };
+        steps.push(step);
+    }
+    steps
+}
+
+fn write_steps(text: &str, steps: &[Step]) -> String {
+    let input = text.as_bytes();
+    let mut output = Vec::new();
+    let mut position = 0;
+    for step in steps {
+        match *step {
+            Step::Literal if input[position] == b'(' => {
+                output.extend_from_slice(b"(1x1)(");
+                position += 1;
+            }
+            Step::Literal => {
+                output.push(input[position]);
+                position += 1;
+            }
+            Step::Repeat { length, repeats } => {
+                output.extend_from_slice(format!("({}x{})", length, repeats).as_bytes());
+                output.extend_from_slice(&input[position..position + length]);
+                position += length * repeats;
+            }
+        }
+    }
+    String::from_utf8(output).expect("markers are only placed between characters")
+}
+
+// version 2 expands marker data again, so the data of every repeat is encoded recursively
+fn encode_nested(text: &str) -> String {
+    let next = next_same(text.as_bytes());
+    let mut output = String::new();
+    let mut position = 0;
+    while position < text.len() {
+        match best_repeat(text, &next, position) {
+            Some(Step::Repeat { length, repeats }) => {
+                let data = encode_nested(&text[position..position + length]);
+                output.push_str(&format!("({}x{})", data.len(), repeats));
+                output.push_str(&data);
+                position += length * repeats;
+            }
+            _ => {
+                let next = text[position..].chars().next().unwrap();
+                output.push(next);
+                position += next.len_utf8();
+            }
+        }
+    }
+    output
+}
+
+pub fn encode(text: &str, mode: Mode) -> Result<String, EncodeError> {
+    if let Some(offset) = text.bytes().position(|byte| byte.is_ascii_whitespace()) {
+        return Err(EncodeError::Whitespace { offset });
+    }
+    match mode {
+        Mode::Greedy => Ok(write_steps(text, &greedy_steps(text))),
+        Mode::Optimal => Ok(write_steps(text, &optimal_steps(text))),
+        Mode::Nested => match text.find('(') {
+            Some(offset) => Err(EncodeError::Unencodable { offset }),
+            None => Ok(encode_nested(text)),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lcg::Lcg;
+    use crate::stream::{Decompressor, Version};
+    use std::io::Read;
+
+    fn decode(input: &str, version: Version) -> String {
+        let mut output = String::new();
+        Decompressor::new(input.as_bytes(), version)
+            .read_to_string(&mut output)
+            .unwrap();
+        output
+    }
+
+    const SAMPLES: &[&str] = &[
+        "",
+        "ADVENT",
+        "AAAAAAAAAAAAAAAAAAAAAAAA",
+        "XYZXYZXYZXYZ",
+        "ABABABABCDCDCDCDABABABABCDCDCDCD",
+        "the cat sat on the mat, the cat sat on the mat, the cat sat on the mat",
+        "(1x5)BC((((((((((((",
+        "zażółć gęślą jaźń jaźń jaźń jaźń",
+    ];
+
+    // the decoder skips whitespace, so the samples are encoded with their spaces replaced
+    fn encodable() -> Vec<String> {
+        SAMPLES
+            .iter()
+            .map(|sample| sample.replace(' ', "_"))
+            .collect()
+    }
+
+    #[test]
+    fn test_round_trip_version_1() {
+        for sample in &encodable() {
+            for mode in &[Mode::Greedy, Mode::Optimal] {
+                let encoded = encode(sample, *mode).unwrap();
+                assert_eq!(*sample, decode(&encoded, Version::One));
+            }
+        }
+    }
+
+    #[test]
+    fn test_round_trip_version_2() {
+        for sample in encodable().iter().filter(|sample| !sample.contains('(')) {
+            let encoded = encode(sample, Mode::Nested).unwrap();
+            assert_eq!(*sample, decode(&encoded, Version::Two));
+        }
+    }
+
+    #[test]
+    fn test_whitespace_is_rejected() {
+        for sample in SAMPLES.iter().filter(|sample| sample.contains(' ')) {
+            for mode in &[Mode::Greedy, Mode::Optimal, Mode::Nested] {
+                assert_eq!(
+                    Err(EncodeError::Whitespace {
+                        offset: sample.find(' ').unwrap()
+                    }),
+                    encode(sample, *mode)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_optimal_is_never_longer() {
+        for sample in &encodable() {
+            let greedy = encode(sample, Mode::Greedy).unwrap();
+            let optimal = encode(sample, Mode::Optimal).unwrap();
+            assert!(optimal.len() <= greedy.len());
+            assert!(optimal.len() <= sample.len() + 5 * sample.matches('(').count());
+        }
+    }
+
+    // the length of the shortest version 1 encoding, by trying every marker at every position
+    fn shortest(input: &[u8]) -> usize {
+        let mut cost = vec![0; input.len() + 1];
+        for i in (0..input.len()).rev() {
+            cost[i] = literal_cost(input[i]) + cost[i + 1];
+            for length in 1..=input.len() - i {
+                let pattern = &input[i..i + length];
+                let mut repeats = 1;
+                while input[i + length * (repeats - 1)..].starts_with(pattern) {
+                    let candidate =
+                        marker_cost(length, repeats) + length + cost[i + length * repeats];
+                    cost[i] = cost[i].min(candidate);
+                    repeats += 1;
+                }
+            }
+        }
+        cost[0]
+    }
+
+    #[test]
+    fn test_optimal_matches_exhaustive_search() {
+        let example = "aaababbaaaaaaaaaaa(aaa(";
+        assert_eq!(22, encode(example, Mode::Optimal).unwrap().len());
+
+        let mut rng = Lcg(42);
+        for _ in 0..20_000 {
+            let length = rng.next(24);
+            let text: String = (0..length).map(|_| b"aab("[rng.next(4)] as char).collect();
+            let encoded = encode(&text, Mode::Optimal).unwrap();
+            assert_eq!(shortest(text.as_bytes()), encoded.len(), "{}", text);
+            assert_eq!(text, decode(&encoded, Version::One));
+        }
+    }
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(
+            Ok(String::from("(1x24)A")),
+            encode(&"A".repeat(24), Mode::Greedy)
+        );
+        assert_eq!(
+            Ok(String::from("(3x4)XYZ")),
+            encode("XYZXYZXYZXYZ", Mode::Optimal)
+        );
+        assert_eq!(
+            Ok(String::from("(14x2)(2x4)AB(2x4)CD")),
+            encode("ABABABABCDCDCDCDABABABABCDCDCDCD", Mode::Nested)
+        );
+        assert_eq!(
+            Err(EncodeError::Unencodable { offset: 1 }),
+            encode("A(", Mode::Nested)
+        );
+        assert_eq!(
+            Err(EncodeError::Whitespace { offset: 3 }),
+            encode("ABC DEF", Mode::Greedy)
+        );
+    }
+
+    #[test]
+    fn test_long_runs() {
+        assert_eq!((1, 1, 5), (digits(0), digits(9), digits(10_000)));
+        let text = "A".repeat(2_000);
+        assert_eq!(9, encode(&text, Mode::Optimal).unwrap().len());
+    }
+
+    #[test]
+    fn test_max_length() {
+        // letters from a small generator, so the block has no repeats of its own
+        let mut rng = Lcg(9);
+        let mut block = |length: usize| -> String {
+            (0..length)
+                .map(|_| (b'a' + rng.next(26) as u8) as char)
+                .collect()
+        };
+
+        let within = block(MAX_LENGTH).repeat(2);
+        let encoded = encode(&within, Mode::Optimal).unwrap();
+        assert!(encoded.len() < MAX_LENGTH + 16);
+
+        // a unit one byte longer than the cap is never looked for
+        let beyond = block(MAX_LENGTH + 1).repeat(2);
+        let encoded = encode(&beyond, Mode::Optimal).unwrap();
+        assert!(encoded.len() > 2 * MAX_LENGTH - 64);
+    }
+}
@@ -1,98 +1,290 @@
+use std::env;
+use std::fmt;
 use std::fs;
+use std::io::{self, BufWriter, Read};
 
-struct Marker {
-    length: usize,
-    multiplier: usize,
-}
+mod encoder;
+#[cfg(test)]
+mod lcg;
+mod parser;
+mod stream;
+mod tree;
 
-fn decompress_part_1(input: &str) -> usize {
-    let mut sum = 0;
+use encoder::Mode;
+use parser::{Compressed, ParseError, Token};
+use stream::{Decompressor, Version};
+use tree::Tree;
 
-    let (head, tail) = read_until_first_marker(input);
-    sum += head.len();
+#[derive(Debug, PartialEq)]
+enum DecompressError {
+    Parse(ParseError),
+    Overflow { offset: usize },
+}
 
-    if let Some((marker, rest)) = tail {
-        (0..marker.multiplier).for_each(|_| sum += rest[0..marker.length].len());
-        sum += decompress_part_1(&rest[marker.length..]);
+impl From<ParseError> for DecompressError {
+    fn from(error: ParseError) -> Self {
+        DecompressError::Parse(error)
     }
-
-    sum
 }
 
-fn decompress_part_2(input: &str) -> usize {
-    let mut sum = 0;
-
-    let (head, tail) = read_until_first_marker(input);
-    sum += head.len();
-
-    if let Some((marker, rest)) = tail {
-        (0..marker.multiplier).for_each(|_| {
-            sum += decompress_part_2(&rest[0..marker.length]);
-        });
-        sum += decompress_part_2(&rest[marker.length..]);
+impl fmt::Display for DecompressError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecompressError::Parse(error) => write!(f, "{}", error),
+            DecompressError::Overflow { offset } => {
+                write!(f, "decompressed length overflows at byte {}", offset)
+            }
+        }
     }
+}
 
-    sum
+fn decompress_part_1(input: &str) -> Result<u128, DecompressError> {
+    let compressed = Compressed::new(input);
+    compressed
+        .tokens(0..compressed.len())
+        .try_fold(0u128, |sum, token| {
+            let (length, offset) = match token? {
+                Token::Literal(range) => {
+                    (Some(range.len() as u128), compressed.offset(range.start))
+                }
+                Token::Marker {
+                    marker,
+                    offset,
+                    data,
+                } => (
+                    (data.len() as u128).checked_mul(marker.multiplier as u128),
+                    offset,
+                ),
+            };
+            length
+                .and_then(|length| sum.checked_add(length))
+                .ok_or(DecompressError::Overflow { offset })
+        })
 }
 
-fn read_until_first_marker(input: &str) -> (&str, Option<(Marker, &str)>) {
-    let marker_start_index = input.find('(');
-
-    if let Some(start_index) = marker_start_index {
-        let marker_end_index = input.find(')');
-
-        if let Some(end_index) = marker_end_index {
-            let marker = &input[start_index + 1..end_index];
-            let mut props = marker.split('x').map(|prop| prop.parse::<usize>().unwrap());
-            return (
-                &input[0..start_index],
-                Some((
-                    Marker {
-                        length: props.next().unwrap(),
-                        multiplier: props.next().unwrap(),
-                    },
-                    &input[end_index + 1..],
-                )),
-            );
+// every byte counts as many times as the product of the multipliers of the markers around it,
+// so a single scan with a stack of those products covers any depth of nesting
+fn decompress_part_2(input: &str) -> Result<u128, DecompressError> {
+    let compressed = Compressed::new(input);
+    let mut stack = vec![(compressed.tokens(0..compressed.len()), 1u128)];
+    let mut sum = 0u128;
+
+    while let Some((tokens, weight)) = stack.last_mut() {
+        let weight = *weight;
+        let token = match tokens.next() {
+            Some(token) => token?,
+            None => {
+                stack.pop();
+                continue;
+            }
+        };
+        match token {
+            Token::Literal(range) => {
+                sum = (range.len() as u128)
+                    .checked_mul(weight)
+                    .and_then(|length| sum.checked_add(length))
+                    .ok_or(DecompressError::Overflow {
+                        offset: compressed.offset(range.start),
+                    })?;
+            }
+            Token::Marker {
+                marker,
+                offset,
+                data,
+            } => {
+                let weight = weight
+                    .checked_mul(marker.multiplier as u128)
+                    .ok_or(DecompressError::Overflow { offset })?;
+                stack.push((compressed.tokens(data), weight));
+            }
         }
     }
+    Ok(sum)
+}
 
-    (input, None)
+fn decompressed_length(input: &str, version: Version) -> u64 {
+    io::copy(
+        &mut Decompressor::new(input.as_bytes(), version),
+        &mut io::sink(),
+    )
+    .expect("failed to decompress")
 }
 
 fn main() {
     let input = fs::read_to_string("input").expect("file not found");
     let input = input.trim();
 
-    assert_eq!(112_830, decompress_part_1(input));
-    assert_eq!(10_931_789_799, decompress_part_2(input));
+    assert_eq!(Ok(112_830), decompress_part_1(input));
+    assert_eq!(Ok(10_931_789_799), decompress_part_2(input));
+
+    assert_eq!(112_830, decompressed_length(input, Version::One));
+    let tree = Tree::new(input, Version::One).expect("invalid input");
+    assert_eq!(112_830, tree.len());
+
+    // a look into the middle of the version 2 output without expanding what comes before it
+    let tree = Tree::new(input, Version::Two).expect("invalid input");
+    assert_eq!(10_931_789_799, tree.len());
+    let middle = tree.len() / 2;
+    let bytes = tree.slice(middle..middle + 40).expect("out of range");
+    assert_eq!(40, bytes.len());
+    assert_eq!(Some(bytes[0]), tree.byte_at(middle));
+    assert_eq!(Some(bytes[39]), tree.byte_at(middle + 39));
+
+    // where the version 2 length comes from
+    let biggest = tree.biggest_contributor().expect("no markers");
+    assert_eq!(
+        (3785, 8, 4861, 4_502_520_526),
+        (
+            biggest.marker.length,
+            biggest.marker.multiplier,
+            biggest.offset,
+            biggest.bytes
+        )
+    );
+
+    // `cargo run -- tree` writes the version 2 marker tree to tree.txt and tree.dot
+    if env::args().nth(1).as_deref() == Some("tree") {
+        fs::write("tree.txt", tree.to_string()).expect("failed to write tree");
+        tree.write_dot(&mut BufWriter::new(
+            fs::File::create("tree.dot").expect("failed to create file"),
+        ))
+        .expect("failed to write graph");
+    }
+
+    // the expanded version 1 text makes a fixture that the encoder has to give back intact
+    let mut expanded = String::new();
+    Decompressor::new(input.as_bytes(), Version::One)
+        .read_to_string(&mut expanded)
+        .expect("failed to decompress");
+    for mode in &[Mode::Greedy, Mode::Optimal] {
+        let encoded = encoder::encode(&expanded, *mode).expect("failed to encode");
+        assert_eq!(
+            expanded.len() as u64,
+            decompressed_length(&encoded, Version::One)
+        );
+        assert!(encoded.len() < expanded.len());
+    }
+
+    // version 2 is far too large to expand here, so only its beginning is used
+    let mut head = String::new();
+    Decompressor::new(input.as_bytes(), Version::Two)
+        .take(1 << 16)
+        .read_to_string(&mut head)
+        .expect("failed to decompress");
+    let nested = encoder::encode(&head, Mode::Nested).expect("failed to encode");
+    assert_eq!(
+        head.len() as u64,
+        decompressed_length(&nested, Version::Two)
+    );
+    assert!(nested.len() < head.len());
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::lcg::Lcg;
 
     #[test]
     fn test_decompress_part_1() {
-        assert_eq!(6, decompress_part_1("ADVENT"));
-        assert_eq!(7, decompress_part_1("A(1x5)BC"));
-        assert_eq!(9, decompress_part_1("(3x3)XYZ"));
-        assert_eq!(11, decompress_part_1("A(2x2)BCD(2x2)EFG"));
-        assert_eq!(6, decompress_part_1("(6x1)(1x3)A"));
-        assert_eq!(18, decompress_part_1("X(8x2)(3x3)ABCY"));
+        assert_eq!(Ok(6), decompress_part_1("ADVENT"));
+        assert_eq!(Ok(7), decompress_part_1("A(1x5)BC"));
+        assert_eq!(Ok(9), decompress_part_1("(3x3)XYZ"));
+        assert_eq!(Ok(11), decompress_part_1("A(2x2)BCD(2x2)EFG"));
+        assert_eq!(Ok(6), decompress_part_1("(6x1)(1x3)A"));
+        assert_eq!(Ok(18), decompress_part_1("X(8x2)(3x3)ABCY"));
     }
 
     #[test]
     fn test_decompress_part_2() {
-        assert_eq!(9, decompress_part_2("(3x3)XYZ"));
-        assert_eq!(20, decompress_part_2("X(8x2)(3x3)ABCY"));
+        assert_eq!(Ok(9), decompress_part_2("(3x3)XYZ"));
+        assert_eq!(Ok(20), decompress_part_2("X(8x2)(3x3)ABCY"));
         assert_eq!(
-            241_920,
+            Ok(241_920),
             decompress_part_2("(27x12)(20x12)(13x14)(7x10)(1x12)A")
         );
         assert_eq!(
-            445,
+            Ok(445),
             decompress_part_2("(25x3)(3x3)ABC(2x3)XY(5x2)PQRSTX(18x9)(3x2)TWO(5x7)SEVEN")
         );
     }
+
+    #[test]
+    fn test_decompress_ignores_whitespace() {
+        assert_eq!(Ok(18), decompress_part_1("X(8x2)(3x3)ABCY\n"));
+        assert_eq!(Ok(20), decompress_part_2("X(8x2) (3x3)\nABC Y"));
+    }
+
+    // built from the inside out so the test does not spend its time on string copies
+    fn nested(depth: usize, multiplier: usize) -> String {
+        let mut markers = Vec::with_capacity(depth);
+        let mut length = 1;
+        for _ in 0..depth {
+            let marker = format!("({}x{})", length, multiplier);
+            length += marker.len();
+            markers.push(marker);
+        }
+        markers.reverse();
+        markers.concat() + "A"
+    }
+
+    #[test]
+    fn test_decompress_deep_nesting() {
+        let input = nested(100_000, 1);
+        assert_eq!(Ok(1), decompress_part_2(&input));
+        let outer_data = input.len() - input.find(')').unwrap() - 1;
+        assert_eq!(Ok(outer_data as u128), decompress_part_1(&input));
+        assert_eq!(Ok(1 << 100), decompress_part_2(&nested(100, 2)));
+        assert_eq!(Ok(0), decompress_part_2(&nested(100_000, 0)));
+    }
+
+    #[test]
+    fn test_decompress_overflow() {
+        // the 128th marker pushes the multiplier past 2^127
+        let input = nested(100_000, 2);
+        let offset = input.match_indices('(').nth(127).unwrap().0;
+        assert_eq!(
+            Err(DecompressError::Overflow { offset }),
+            decompress_part_2(&input)
+        );
+        assert!(DecompressError::Overflow { offset }
+            .to_string()
+            .starts_with("decompressed length overflows"));
+    }
+
+    #[test]
+    fn test_decompress_malformed() {
+        assert_eq!(
+            Err(DecompressError::Parse(ParseError::MalformedMarker {
+                offset: 0
+            })),
+            decompress_part_1("(3x3")
+        );
+        assert_eq!(
+            Err(DecompressError::Parse(ParseError::DataPastEnd {
+                offset: 6,
+                length: 4,
+                available: 2
+            })),
+            decompress_part_2("(8x2)A(4x3)ABCY")
+        );
+    }
+
+    // short random inputs over the marker alphabet, most of them malformed
+    #[test]
+    fn test_stream_agrees_with_part_2() {
+        let mut rng = Lcg(43);
+        for _ in 0..200_000 {
+            let length = rng.next(16);
+            let input: String = (0..length)
+                .map(|_| b"(x)0123AB"[rng.next(9)] as char)
+                .collect();
+
+            let mut decompressor = Decompressor::new(input.as_bytes(), Version::Two);
+            let streamed = io::copy(&mut decompressor, &mut io::sink());
+            match decompress_part_2(&input) {
+                Ok(length) => assert_eq!(length as u64, streamed.unwrap(), "{}", input),
+                Err(_) => assert!(streamed.is_err(), "{}", input),
+            }
+        }
+    }
 }
@@ -0,0 +1,294 @@
+use std::fmt;
+use std::ops::Range;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Marker {
+    pub length: usize,
+    pub multiplier: usize,
+}
+
+// offsets are bytes into the original input, whitespace included
+#[derive(Debug, PartialEq)]
+pub enum ParseError {
+    MalformedMarker {
+        offset: usize,
+    },
+    NonNumericField {
+        offset: usize,
+    },
+    FieldOutOfRange {
+        offset: usize,
+    },
+    DataPastEnd {
+        offset: usize,
+        length: usize,
+        available: usize,
+    },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::MalformedMarker { offset } => {
+                write!(f, "malformed marker at byte {}", offset)
+            }
+            ParseError::NonNumericField { offset } => {
+                write!(f, "marker field at byte {} is not a number", offset)
+            }
+            ParseError::FieldOutOfRange { offset } => {
+                write!(f, "marker field at byte {} is too large", offset)
+            }
+            ParseError::DataPastEnd {
+                offset,
+                length,
+                available,
+            } => write!(
+                f,
+                "marker at byte {} covers {} bytes but only {} are left",
+                offset, length, available
+            ),
+        }
+    }
+}
+
+// ranges index into the compressed bytes with whitespace already taken out
+#[derive(Clone, Debug, PartialEq)]
+pub enum Token {
+    Literal(Range<usize>),
+    Marker {
+        marker: Marker,
+        offset: usize,
+        data: Range<usize>,
+    },
+}
+
+// the puzzle ignores whitespace, so it is dropped up front and only remembered for offsets
+pub struct Compressed {
+    bytes: Vec<u8>,
+    offsets: Vec<usize>,
+    original_length: usize,
+}
+
+impl Compressed {
+    pub fn new(input: &str) -> Self {
+        let (offsets, bytes) = input
+            .bytes()
+            .enumerate()
+            .filter(|(_, byte)| !byte.is_ascii_whitespace())
+            .unzip();
+        Compressed {
+            bytes,
+            offsets,
+            original_length: input.len(),
+        }
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn offset(&self, index: usize) -> usize {
+        self.offsets
+            .get(index)
+            .copied()
+            .unwrap_or(self.original_length)
+    }
+
+    pub fn tokens(&self, range: Range<usize>) -> Tokens<'_> {
+        Tokens {
+            compressed: self,
+            position: range.start,
+            end: range.end,
+        }
+    }
+
+    fn field(&self, start: usize, end: usize) -> Result<usize, ParseError> {
+        let digits = &self.bytes[start..end];
+        if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) {
+            return Err(ParseError::NonNumericField {
+                offset: self.offset(start),
+            });
+        }
+        digits.iter().try_fold(0usize, |value, digit| {
+            value
+                .checked_mul(10)
+                .and_then(|value| value.checked_add(usize::from(digit - b'0')))
+                .ok_or(ParseError::FieldOutOfRange {
+                    offset: self.offset(start),
+                })
+        })
+    }
+}
+
+pub struct Tokens<'a> {
+    compressed: &'a Compressed,
+    position: usize,
+    end: usize,
+}
+
+impl Tokens<'_> {
+    fn marker(&mut self) -> Result<Token, ParseError> {
+        let bytes = &self.compressed.bytes[..self.end];
+        let start = self.position;
+        let malformed = ParseError::MalformedMarker {
+            offset: self.compressed.offset(start),
+        };
+        let field_end = |from: usize| {
+            (from..bytes.len())
+                .find(|i| matches!(bytes[*i], b'x' | b')' | b'('))
+                .unwrap_or(bytes.len())
+        };
+
+        let x = field_end(start + 1);
+        if bytes.get(x) != Some(&b'x') {
+            return Err(malformed);
+        }
+        let close = field_end(x + 1);
+        if bytes.get(close) != Some(&b')') {
+            return Err(malformed);
+        }
+        let marker = Marker {
+            length: self.compressed.field(start + 1, x)?,
+            multiplier: self.compressed.field(x + 1, close)?,
+        };
+
+        let available = bytes.len() - (close + 1);
+        if marker.length > available {
+            return Err(ParseError::DataPastEnd {
+                offset: self.compressed.offset(start),
+                length: marker.length,
+                available,
+            });
+        }
+        let data = close + 1..close + 1 + marker.length;
+        self.position = data.end;
+        Ok(Token::Marker {
+            marker,
+            offset: self.compressed.offset(start),
+            data,
+        })
+    }
+}
+
+impl Iterator for Tokens<'_> {
+    type Item = Result<Token, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.position >= self.end {
+            return None;
+        }
+        if self.compressed.bytes[self.position] == b'(' {
+            let token = self.marker();
+            if token.is_err() {
+                self.position = self.end;
+            }
+            return Some(token);
+        }
+
+        let start = self.position;
+        self.position = self.compressed.bytes[start..self.end]
+            .iter()
+            .position(|byte| *byte == b'(')
+            .map_or(self.end, |i| start + i);
+        Some(Ok(Token::Literal(start..self.position)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(input: &str) -> Result<Vec<Token>, ParseError> {
+        let compressed = Compressed::new(input);
+        compressed.tokens(0..compressed.len()).collect()
+    }
+
+    #[test]
+    fn test_tokens() {
+        assert_eq!(
+            Ok(vec![
+                Token::Literal(0..1),
+                Token::Marker {
+                    marker: Marker {
+                        length: 8,
+                        multiplier: 2
+                    },
+                    offset: 1,
+                    data: 6..14
+                },
+                Token::Literal(14..15)
+            ]),
+            tokens("X(8x2)(3x3)ABCY")
+        );
+        assert_eq!(Ok(vec![Token::Literal(0..4)]), tokens("A)B)"));
+        assert_eq!(Ok(vec![]), tokens(""));
+    }
+
+    #[test]
+    fn test_whitespace_is_ignored() {
+        let plain = tokens("X(8x2)(3x3)ABCY").unwrap();
+        let spaced = tokens(" X(8x2)\n(3x 3)A B\tCY\n").unwrap();
+        assert_eq!(plain[0], spaced[0]);
+        assert_eq!(plain[2], spaced[2]);
+        // only the offset, which points into the original input, tells them apart
+        assert_eq!(
+            Token::Marker {
+                marker: Marker {
+                    length: 8,
+                    multiplier: 2
+                },
+                offset: 2,
+                data: 6..14
+            },
+            spaced[1]
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            Err(ParseError::MalformedMarker { offset: 2 }),
+            tokens("AB(3x3")
+        );
+        assert_eq!(
+            Err(ParseError::MalformedMarker { offset: 0 }),
+            tokens("(3y3)ABC")
+        );
+        assert_eq!(
+            Err(ParseError::MalformedMarker { offset: 1 }),
+            tokens("A(3(1x1)x3)B")
+        );
+        assert_eq!(
+            Err(ParseError::NonNumericField { offset: 3 }),
+            tokens("A (ax3)BCD")
+        );
+        assert_eq!(
+            Err(ParseError::NonNumericField { offset: 3 }),
+            tokens("(1x)B")
+        );
+        assert_eq!(
+            Err(ParseError::FieldOutOfRange { offset: 1 }),
+            tokens("(99999999999999999999999x1)A")
+        );
+        assert_eq!(
+            Err(ParseError::DataPastEnd {
+                offset: 0,
+                length: 3,
+                available: 2
+            }),
+            tokens("(3x3)A B")
+        );
+    }
+
+    #[test]
+    fn test_error_display() {
+        assert_eq!(
+            "marker at byte 0 covers 3 bytes but only 2 are left",
+            tokens("(3x3)AB").unwrap_err().to_string()
+        );
+    }
+}
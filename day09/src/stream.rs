@@ -0,0 +1,298 @@
+use crate::parser::Marker;
+use std::io::{self, BufRead, BufReader, Read};
+use std::rc::Rc;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Version {
+    One,
+    Two,
+}
+
+// the data section of a marker, replayed `repeats` times; nested markers point into the same buffer
+struct Frame {
+    data: Rc<[u8]>,
+    start: usize,
+    end: usize,
+    position: usize,
+    repeats: usize,
+}
+
+// expands the compressed input lazily: only the data sections of the markers currently being
+// repeated are kept in memory, never the output
+pub struct Decompressor<R> {
+    input: BufReader<R>,
+    version: Version,
+    frames: Vec<Frame>,
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// reads the rest of a marker once its opening parenthesis has been consumed
+fn parse_marker<F>(mut next: F) -> io::Result<Marker>
+where
+    F: FnMut() -> io::Result<Option<u8>>,
+{
+    let mut number = |terminator: u8| -> io::Result<usize> {
+        let mut value: usize = 0;
+        let mut digits = 0;
+        loop {
+            match next()? {
+                Some(byte) if byte == terminator && digits > 0 => return Ok(value),
+                Some(byte @ b'0'..=b'9') => {
+                    value = value
+                        .checked_mul(10)
+                        .and_then(|value| value.checked_add(usize::from(byte - b'0')))
+                        .ok_or_else(|| invalid("marker field too large"))?;
+                    digits += 1;
+                }
+                Some(_) => return Err(invalid("malformed marker")),
+                None => return Err(invalid("unterminated marker")),
+            }
+        }
+    };
+
+    Ok(Marker {
+        length: number(b'x')?,
+        multiplier: number(b')')?,
+    })
+}
+
+// a marker repeated zero times produces nothing, but in version 2 its data still has to parse,
+// nested markers included
+fn validate(data: &[u8]) -> io::Result<()> {
+    let mut ends = vec![data.len()];
+    let mut position = 0;
+    while position < data.len() {
+        while ends.last() == Some(&position) {
+            ends.pop();
+        }
+        let end = *ends.last().unwrap_or(&data.len());
+        if data[position] != b'(' {
+            position += 1;
+            continue;
+        }
+        let mut bytes = data[position + 1..end].iter();
+        let marker = parse_marker(|| Ok(bytes.next().copied()))?;
+        let start = end - bytes.len();
+        if marker.length > bytes.len() {
+            return Err(invalid("marker data runs past the enclosing marker"));
+        }
+        ends.push(start + marker.length);
+        position = start;
+    }
+    Ok(())
+}
+
+impl<R: Read> Decompressor<R> {
+    pub fn new(input: R, version: Version) -> Self {
+        Decompressor {
+            input: BufReader::new(input),
+            version,
+            frames: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, data: Rc<[u8]>, start: usize, marker: Marker) -> io::Result<()> {
+        if self.version == Version::Two && marker.multiplier == 0 {
+            validate(&data[start..start + marker.length])?;
+        }
+        if marker.length > 0 && marker.multiplier > 0 {
+            self.frames.push(Frame {
+                data,
+                start,
+                end: start + marker.length,
+                position: start,
+                repeats: marker.multiplier,
+            });
+        }
+        Ok(())
+    }
+
+    // copies the next run of output into `buf`, Ok(None) once the input is exhausted
+    fn step(&mut self, buf: &mut [u8]) -> io::Result<Option<usize>> {
+        let version = self.version;
+        let frame = match self.frames.last_mut() {
+            Some(frame) => frame,
+            None => return self.step_input(buf),
+        };
+
+        if frame.position == frame.end {
+            frame.repeats -= 1;
+            frame.position = frame.start;
+            if frame.repeats == 0 {
+                self.frames.pop();
+            }
+            return Ok(Some(0));
+        }
+
+        let data = &frame.data[frame.position..frame.end];
+        if version == Version::Two && data[0] == b'(' {
+            let mut bytes = data[1..].iter();
+            let marker = parse_marker(|| Ok(bytes.next().copied()))?;
+            let start = frame.end - bytes.len();
+            if marker.length > bytes.len() {
+                return Err(invalid("marker data runs past the enclosing marker"));
+            }
+            frame.position = start + marker.length;
+            let data = Rc::clone(&frame.data);
+            self.push(data, start, marker)?;
+            return Ok(Some(0));
+        }
+
+        let run = match version {
+            Version::One => data.len(),
+            Version::Two => data.iter().position(|b| *b == b'(').unwrap_or(data.len()),
+        };
+        let n = run.min(buf.len());
+        buf[..n].copy_from_slice(&data[..n]);
+        frame.position += n;
+        Ok(Some(n))
+    }
+
+    fn step_input(&mut self, buf: &mut [u8]) -> io::Result<Option<usize>> {
+        let available = self.input.fill_buf()?;
+        match available.first() {
+            None => Ok(None),
+            Some(byte) if byte.is_ascii_whitespace() => {
+                let skipped = available
+                    .iter()
+                    .take_while(|b| b.is_ascii_whitespace())
+                    .count();
+                self.input.consume(skipped);
+                Ok(Some(0))
+            }
+            Some(b'(') => {
+                self.input.consume(1);
+                let marker = parse_marker(|| next_byte(&mut self.input))?;
+                let data = (0..marker.length)
+                    .map(|_| {
+                        next_byte(&mut self.input)?
+                            .ok_or_else(|| invalid("marker data runs past the end of the input"))
+                    })
+                    .collect::<io::Result<Vec<u8>>>()?;
+                self.push(data.into(), 0, marker)?;
+                Ok(Some(0))
+            }
+            Some(_) => {
+                let run = available
+                    .iter()
+                    .position(|b| *b == b'(' || b.is_ascii_whitespace())
+                    .unwrap_or(available.len());
+                let n = run.min(buf.len());
+                buf[..n].copy_from_slice(&available[..n]);
+                self.input.consume(n);
+                Ok(Some(n))
+            }
+        }
+    }
+}
+
+// the puzzle ignores whitespace, so it never reaches a marker's data
+fn next_byte<R: Read>(input: &mut BufReader<R>) -> io::Result<Option<u8>> {
+    loop {
+        let byte = input.fill_buf()?.first().copied();
+        input.consume(byte.map_or(0, |_| 1));
+        match byte {
+            Some(byte) if byte.is_ascii_whitespace() => continue,
+            byte => return Ok(byte),
+        }
+    }
+}
+
+impl<R: Read> Read for Decompressor<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut written = 0;
+        while written < buf.len() {
+            match self.step(&mut buf[written..])? {
+                Some(n) => written += n,
+                None => break,
+            }
+        }
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decompress(input: &str, version: Version) -> io::Result<String> {
+        let mut output = String::new();
+        Decompressor::new(input.as_bytes(), version).read_to_string(&mut output)?;
+        Ok(output)
+    }
+
+    #[test]
+    fn test_version_one() {
+        assert_eq!("ADVENT", decompress("ADVENT", Version::One).unwrap());
+        assert_eq!("ABBBBBC", decompress("A(1x5)BC", Version::One).unwrap());
+        assert_eq!("XYZXYZXYZ", decompress("(3x3)XYZ", Version::One).unwrap());
+        assert_eq!(
+            "ABCBCDEFEFG",
+            decompress("A(2x2)BCD(2x2)EFG", Version::One).unwrap()
+        );
+        assert_eq!("(1x3)A", decompress("(6x1)(1x3)A", Version::One).unwrap());
+        assert_eq!(
+            "X(3x3)ABC(3x3)ABCY",
+            decompress("X(8x2)(3x3)ABCY", Version::One).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_version_two() {
+        assert_eq!("XYZXYZXYZ", decompress("(3x3)XYZ", Version::Two).unwrap());
+        assert_eq!(
+            "XABCABCABCABCABCABCY",
+            decompress("X(8x2)(3x3)ABCY", Version::Two).unwrap()
+        );
+        assert_eq!(
+            "A".repeat(241_920),
+            decompress("(27x12)(20x12)(13x14)(7x10)(1x12)A", Version::Two).unwrap()
+        );
+        assert_eq!("AB", decompress("A(0x9)B(3x0)CDE", Version::Two).unwrap());
+        assert_eq!("", decompress("(11x0)(6x1)(1x2)A", Version::Two).unwrap());
+        assert_eq!("B", decompress("(4x0)(2x(B", Version::One).unwrap());
+        assert_eq!(
+            "XABCABCABCABCABCABCY",
+            decompress(" X(8x2)\n(3x 3)A B\tCY\n", Version::Two).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_small_reads() {
+        let mut decompressor = Decompressor::new(&b"X(8x2)(3x3)ABCY"[..], Version::Two);
+        let mut output = Vec::new();
+        let mut buf = [0; 1];
+        while decompressor.read(&mut buf).unwrap() == 1 {
+            output.push(buf[0]);
+        }
+        assert_eq!(b"XABCABCABCABCABCABCY".to_vec(), output);
+    }
+
+    #[test]
+    fn test_copy_matches_length() {
+        let input = "(25x3)(3x3)ABC(2x3)XY(5x2)PQRSTX(18x9)(3x2)TWO(5x7)SEVEN";
+        let mut decompressor = Decompressor::new(input.as_bytes(), Version::Two);
+        let copied = io::copy(&mut decompressor, &mut io::sink()).unwrap();
+        assert_eq!(445, copied);
+    }
+
+    #[test]
+    fn test_malformed() {
+        for input in &[
+            "(3x3",
+            "(3y3)ABC",
+            "(x3)ABC",
+            "(3x3)AB",
+            "(8x2)(9x3)ABCDEF",
+            "(2x0)(2x1)",
+            "(9x0)(6x1)(3x1",
+            "(4x0)(2x(B",
+        ] {
+            let error = decompress(input, Version::Two).unwrap_err();
+            assert_eq!(io::ErrorKind::InvalidData, error.kind());
+        }
+    }
+}
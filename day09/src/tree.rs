@@ -0,0 +1,421 @@
+use crate::parser::{Compressed, Marker, Token};
+use crate::stream::Version;
+use crate::DecompressError;
+use std::fmt;
+use std::io::{self, Write};
+use std::ops::Range;
+
+const LABEL_WIDTH: usize = 16;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Kind {
+    Root,
+    Literal(Range<usize>),
+    Marker { marker: Marker, offset: usize },
+}
+
+// nodes live in one arena and refer to their children by index, so neither building, walking
+// nor dropping a deeply nested tree recurses
+#[derive(Debug)]
+pub struct Node {
+    pub kind: Kind,
+    pub children: Vec<usize>,
+    // expanded length of a single pass over the children, and of the whole node
+    pub unit: u128,
+    pub length: u128,
+    starts: Vec<u128>,
+}
+
+pub struct Tree {
+    compressed: Compressed,
+    nodes: Vec<Node>,
+}
+
+// the bytes a marker adds to the final output over what it would give with a multiplier of 1
+#[derive(Debug, PartialEq)]
+pub struct Contribution {
+    pub node: usize,
+    pub marker: Marker,
+    pub offset: usize,
+    pub bytes: u128,
+}
+
+impl Node {
+    fn new(kind: Kind) -> Self {
+        let length = match &kind {
+            Kind::Literal(range) => range.len() as u128,
+            _ => 0,
+        };
+        Node {
+            kind,
+            children: Vec::new(),
+            unit: length,
+            length,
+            starts: Vec::new(),
+        }
+    }
+}
+
+impl Tree {
+    pub fn new(input: &str, version: Version) -> Result<Self, DecompressError> {
+        let compressed = Compressed::new(input);
+        let mut nodes = vec![Node::new(Kind::Root)];
+        let mut stack = vec![(0, compressed.tokens(0..compressed.len()))];
+
+        while let Some((parent, tokens)) = stack.last_mut() {
+            let parent = *parent;
+            let token = match tokens.next() {
+                Some(token) => token?,
+                None => {
+                    stack.pop();
+                    Tree::finish(&mut nodes, parent)?;
+                    continue;
+                }
+            };
+
+            let index = nodes.len();
+            nodes[parent].children.push(index);
+            match token {
+                Token::Literal(range) => nodes.push(Node::new(Kind::Literal(range))),
+                Token::Marker {
+                    marker,
+                    offset,
+                    data,
+                } => {
+                    nodes.push(Node::new(Kind::Marker { marker, offset }));
+                    match version {
+                        // version 1 never looks inside the data, it is a single literal
+                        Version::One => {
+                            nodes[index].children.push(index + 1);
+                            nodes.push(Node::new(Kind::Literal(data)));
+                            Tree::finish(&mut nodes, index)?;
+                        }
+                        Version::Two => stack.push((index, compressed.tokens(data))),
+                    }
+                }
+            }
+        }
+
+        Ok(Tree { compressed, nodes })
+    }
+
+    // children are complete by the time their parent is finished
+    fn finish(nodes: &mut [Node], index: usize) -> Result<(), DecompressError> {
+        let mut starts = Vec::with_capacity(nodes[index].children.len());
+        let mut unit: u128 = 0;
+        for child in nodes[index].children.iter() {
+            starts.push(unit);
+            unit = unit
+                .checked_add(nodes[*child].length)
+                .ok_or_else(|| overflow(nodes, *child))?;
+        }
+
+        let multiplier = match nodes[index].kind {
+            Kind::Marker { marker, .. } => marker.multiplier as u128,
+            _ => 1,
+        };
+        let length = unit
+            .checked_mul(multiplier)
+            .ok_or_else(|| overflow(nodes, index))?;
+        let node = &mut nodes[index];
+        node.length = length;
+        node.unit = unit;
+        node.starts = starts;
+        Ok(())
+    }
+
+    pub fn len(&self) -> u128 {
+        self.nodes[0].length
+    }
+
+    // the compressed byte that lands at `position` of the output, and how many follow it in the
+    // same literal run
+    fn locate(&self, position: u128) -> Option<(usize, usize)> {
+        if position >= self.len() {
+            return None;
+        }
+
+        let mut index = 0;
+        let mut position = position;
+        loop {
+            let node = &self.nodes[index];
+            if let Kind::Literal(range) = &node.kind {
+                let start = range.start + position as usize;
+                return Some((start, range.end - start));
+            }
+            position %= node.unit;
+            let child = node.starts.partition_point(|start| *start <= position) - 1;
+            position -= node.starts[child];
+            index = node.children[child];
+        }
+    }
+
+    pub fn byte_at(&self, position: u128) -> Option<u8> {
+        self.locate(position)
+            .map(|(index, _)| self.compressed.bytes()[index])
+    }
+
+    pub fn slice(&self, range: Range<u128>) -> Option<Vec<u8>> {
+        if range.start > range.end || range.end > self.len() {
+            return None;
+        }
+
+        let mut output = Vec::with_capacity((range.end - range.start) as usize);
+        let mut position = range.start;
+        while position < range.end {
+            let (index, run) = self.locate(position)?;
+            let take = run.min((range.end - position) as usize);
+            output.extend_from_slice(&self.compressed.bytes()[index..index + take]);
+            position += take as u128;
+        }
+        Some(output)
+    }
+
+    fn label(&self, index: usize) -> String {
+        let node = &self.nodes[index];
+        match &node.kind {
+            Kind::Root => format!("root: {} bytes", node.length),
+            Kind::Literal(range) => {
+                let text = String::from_utf8_lossy(&self.compressed.bytes()[range.clone()]);
+                let text: String = if text.chars().count() > LABEL_WIDTH {
+                    text.chars()
+                        .take(LABEL_WIDTH)
+                        .chain("...".chars())
+                        .collect()
+                } else {
+                    text.into_owned()
+                };
+                format!("{:?}: {} bytes", text, node.length)
+            }
+            Kind::Marker { marker, offset } => format!(
+                "({}x{}) at byte {}: {} bytes",
+                marker.length, marker.multiplier, offset, node.length
+            ),
+        }
+    }
+
+    // nodes in the order they appear in the input, each with its depth
+    fn walk(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let mut stack = vec![(0, 0)];
+        std::iter::from_fn(move || {
+            let (index, depth) = stack.pop()?;
+            let children = self.nodes[index].children.iter().rev();
+            stack.extend(children.map(|child| (*child, depth + 1)));
+            Some((index, depth))
+        })
+    }
+
+    pub fn biggest_contributor(&self) -> Option<Contribution> {
+        // weight is how many times the enclosing markers repeat a node
+        let mut weights = vec![0u128; self.nodes.len()];
+        weights[0] = 1;
+        let mut best: Option<Contribution> = None;
+
+        for (index, _) in self.walk() {
+            let node = &self.nodes[index];
+            let weight = weights[index];
+            let multiplier = match node.kind {
+                Kind::Marker { marker, offset } => {
+                    let bytes = weight
+                        .saturating_mul(node.unit)
+                        .saturating_mul(marker.multiplier.saturating_sub(1) as u128);
+                    if best.as_ref().is_none_or(|best| bytes > best.bytes) {
+                        best = Some(Contribution {
+                            node: index,
+                            marker,
+                            offset,
+                            bytes,
+                        });
+                    }
+                    marker.multiplier as u128
+                }
+                _ => 1,
+            };
+            for child in node.children.iter() {
+                weights[*child] = weight.saturating_mul(multiplier);
+            }
+        }
+        best
+    }
+
+    pub fn write_dot<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let biggest = self
+            .biggest_contributor()
+            .map(|contribution| contribution.node);
+        writeln!(writer, "digraph markers {{")?;
+        writeln!(writer, "    node [shape=box, fontname=monospace];")?;
+        for (index, node) in self.nodes.iter().enumerate() {
+            let style = if Some(index) == biggest {
+                ", style=filled, fillcolor=orange"
+            } else {
+                ""
+            };
+            let label = self.label(index).replace('\\', "\\\\").replace('"', "\\\"");
+            writeln!(writer, "    n{} [label=\"{}\"{}];", index, label, style)?;
+            for child in node.children.iter() {
+                writeln!(writer, "    n{} -> n{};", index, child)?;
+            }
+        }
+        writeln!(writer, "}}")
+    }
+}
+
+impl fmt::Display for Tree {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, depth) in self.walk() {
+            writeln!(f, "{}{}", "  ".repeat(depth), self.label(index))?;
+        }
+        Ok(())
+    }
+}
+
+fn overflow(nodes: &[Node], index: usize) -> DecompressError {
+    let offset = match nodes[index].kind {
+        Kind::Marker { offset, .. } => offset,
+        _ => 0,
+    };
+    DecompressError::Overflow { offset }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::Decompressor;
+    use std::io::Read;
+
+    fn expand(input: &str, version: Version) -> Vec<u8> {
+        let mut output = Vec::new();
+        Decompressor::new(input.as_bytes(), version)
+            .read_to_end(&mut output)
+            .unwrap();
+        output
+    }
+
+    const SAMPLES: &[&str] = &[
+        "ADVENT",
+        "A(1x5)BC",
+        "A(2x2)BCD(2x2)EFG",
+        "X(8x2)(3x3)ABCY",
+        "A(0x9)B(3x0)CDE",
+        "(27x12)(20x12)(13x14)(7x10)(1x12)A",
+        "(25x3)(3x3)ABC(2x3)XY(5x2)PQRSTX(18x9)(3x2)TWO(5x7)SEVEN",
+    ];
+
+    #[test]
+    fn test_matches_stream() {
+        for sample in SAMPLES {
+            for version in &[Version::One, Version::Two] {
+                let tree = Tree::new(sample, *version).unwrap();
+                let expanded = expand(sample, *version);
+                assert_eq!(expanded.len() as u128, tree.len());
+                for (position, byte) in expanded.iter().enumerate() {
+                    assert_eq!(Some(*byte), tree.byte_at(position as u128));
+                }
+                assert_eq!(None, tree.byte_at(tree.len()));
+                assert_eq!(Some(expanded.clone()), tree.slice(0..tree.len()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_slice() {
+        let tree = Tree::new(
+            "(25x3)(3x3)ABC(2x3)XY(5x2)PQRSTX(18x9)(3x2)TWO(5x7)SEVEN",
+            Version::Two,
+        )
+        .unwrap();
+        assert_eq!(Some(b"ABCXYXYXYP".to_vec()), tree.slice(6..16));
+        assert_eq!(Some(Vec::new()), tree.slice(7..7));
+        assert_eq!(None, tree.slice(440..446));
+    }
+
+    #[test]
+    fn test_deep_nesting() {
+        let mut input = String::from("A");
+        for _ in 0..20 {
+            input = format!("({}x2){}", input.len(), input);
+        }
+        let tree = Tree::new(&input, Version::Two).unwrap();
+        assert_eq!(1 << 20, tree.len());
+        assert_eq!(Some(b'A'), tree.byte_at((1 << 20) - 1));
+    }
+
+    #[test]
+    fn test_overflow() {
+        let mut input = String::from("A");
+        for _ in 0..130 {
+            input = format!("({}x2){}", input.len(), input);
+        }
+        assert!(matches!(
+            Tree::new(&input, Version::Two),
+            Err(DecompressError::Overflow { .. })
+        ));
+    }
+
+    #[test]
+    fn test_display() {
+        let tree = Tree::new("X(8x2)(3x3)ABCY", Version::Two).unwrap();
+        assert_eq!(
+            "root: 20 bytes\n\
+             \x20 \"X\": 1 bytes\n\
+             \x20 (8x2) at byte 1: 18 bytes\n\
+             \x20   (3x3) at byte 6: 9 bytes\n\
+             \x20     \"ABC\": 3 bytes\n\
+             \x20 \"Y\": 1 bytes\n",
+            tree.to_string()
+        );
+
+        let tree = Tree::new("(20x2)ABCDEFGHIJKLMNOPQRST", Version::One).unwrap();
+        assert!(tree
+            .to_string()
+            .contains("\"ABCDEFGHIJKLMNOP...\": 20 bytes"));
+    }
+
+    #[test]
+    fn test_biggest_contributor() {
+        let tree = Tree::new(
+            "(25x3)(3x3)ABC(2x3)XY(5x2)PQRSTX(18x9)(3x2)TWO(5x7)SEVEN",
+            Version::Two,
+        )
+        .unwrap();
+        // (18x9) expands to 41 bytes, 8 times more than it would once
+        assert_eq!(
+            Some(Contribution {
+                node: 9,
+                marker: Marker {
+                    length: 18,
+                    multiplier: 9
+                },
+                offset: 32,
+                bytes: 328
+            }),
+            tree.biggest_contributor()
+        );
+        assert_eq!(
+            None,
+            Tree::new("ADVENT", Version::Two)
+                .unwrap()
+                .biggest_contributor()
+        );
+    }
+
+    #[test]
+    fn test_write_dot() {
+        let tree = Tree::new("A(3x2)\"\\B", Version::Two).unwrap();
+        let mut dot = Vec::new();
+        tree.write_dot(&mut dot).unwrap();
+        assert_eq!(
+            "digraph markers {\n    \
+             node [shape=box, fontname=monospace];\n    \
+             n0 [label=\"root: 7 bytes\"];\n    \
+             n0 -> n1;\n    \
+             n0 -> n2;\n    \
+             n1 [label=\"\\\"A\\\": 1 bytes\"];\n    \
+             n2 [label=\"(3x2) at byte 1: 6 bytes\", style=filled, fillcolor=orange];\n    \
+             n2 -> n3;\n    \
+             n3 [label=\"\\\"\\\\\\\"\\\\\\\\B\\\": 3 bytes\"];\n\
+             }\n",
+            String::from_utf8(dot).unwrap()
+        );
+    }
+}
@@ -0,0 +1,118 @@
+// `step` is the position of the event in the log, so events can be ordered across bots and outputs
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Event {
+    Comparison {
+        step: usize,
+        bot: usize,
+        low: usize,
+        high: usize,
+    },
+    Delivery {
+        step: usize,
+        output: usize,
+        value: usize,
+    },
+}
+
+#[derive(Default)]
+pub struct Log {
+    events: Vec<Event>,
+}
+
+impl Log {
+    pub fn new() -> Self {
+        Log::default()
+    }
+
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    pub fn compare(&mut self, bot: usize, low: usize, high: usize) {
+        self.events.push(Event::Comparison {
+            step: self.events.len(),
+            bot,
+            low,
+            high,
+        });
+    }
+
+    pub fn deliver(&mut self, output: usize, value: usize) {
+        self.events.push(Event::Delivery {
+            step: self.events.len(),
+            output,
+            value,
+        });
+    }
+
+    // the bot that compared the two chips, in either order
+    pub fn comparer(&self, a: usize, b: usize) -> Option<usize> {
+        let (low, high) = if a < b { (a, b) } else { (b, a) };
+        self.events.iter().find_map(|event| match *event {
+            Event::Comparison {
+                bot,
+                low: l,
+                high: h,
+                ..
+            } if l == low && h == high => Some(bot),
+            _ => None,
+        })
+    }
+
+    // every chip the bot held, in the order it compared them
+    pub fn passed_through(&self, bot: usize) -> Vec<usize> {
+        self.events
+            .iter()
+            .flat_map(|event| match *event {
+                Event::Comparison {
+                    bot: b, low, high, ..
+                } if b == bot => vec![low, high],
+                _ => vec![],
+            })
+            .collect()
+    }
+
+    pub fn delivered_to(&self, output: usize) -> Vec<usize> {
+        self.events
+            .iter()
+            .filter_map(|event| match *event {
+                Event::Delivery {
+                    output: o, value, ..
+                } if o == output => Some(value),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_queries() {
+        let mut log = Log::new();
+        log.compare(2, 2, 5);
+        log.compare(1, 2, 3);
+        log.deliver(1, 2);
+        log.compare(0, 3, 5);
+        log.deliver(2, 3);
+        log.deliver(0, 5);
+
+        assert_eq!(Some(2), log.comparer(5, 2));
+        assert_eq!(Some(2), log.comparer(2, 5));
+        assert_eq!(Some(0), log.comparer(3, 5));
+        assert_eq!(None, log.comparer(2, 7));
+        assert_eq!(vec![2, 3], log.passed_through(1));
+        assert_eq!(Vec::<usize>::new(), log.passed_through(9));
+        assert_eq!(vec![5], log.delivered_to(0));
+        assert_eq!(
+            Event::Delivery {
+                step: 4,
+                output: 2,
+                value: 3
+            },
+            log.events()[4]
+        );
+    }
+}
@@ -1,26 +1,12 @@
-use regex::Regex;
-use std::collections::HashMap;
-use std::fs;
-
-enum Destination {
-    Bot(usize),
-    Output(usize),
-}
+mod events;
+mod network;
 
-struct Low(Destination);
-struct High(Destination);
-
-struct Instruction {
-    bot: usize,
-    low: Low,
-    high: High,
-}
-
-impl Instruction {
-    fn new(bot: usize, low: Low, high: High) -> Self {
-        Instruction { bot, low, high }
-    }
-}
+use events::Log;
+use network::{Destination, Instruction, Network, Problem};
+use std::collections::{HashMap, VecDeque};
+use std::env;
+use std::fs;
+use std::io::BufWriter;
 
 #[derive(Default)]
 struct Bot {
@@ -29,12 +15,16 @@ struct Bot {
 }
 
 impl Bot {
-    fn set_value(&mut self, value: usize) {
+    // false when the bot already holds two chips, which leaves it as it was
+    fn set_value(&mut self, value: usize) -> bool {
         if self.microchip_1.is_none() {
             self.microchip_1 = Some(value);
         } else if self.microchip_2.is_none() {
             self.microchip_2 = Some(value);
+        } else {
+            return false;
         }
+        true
     }
 
     fn can_proceed(&self) -> bool {
@@ -55,107 +45,213 @@ impl Bot {
     }
 }
 
+// a bot goes on the queue when it gets its second chip, so it fires once per pair
+fn give(
+    destination: &Destination,
+    value: usize,
+    bots: &mut HashMap<usize, Bot>,
+    outputs: &mut HashMap<usize, usize>,
+    log: &mut Log,
+    ready: &mut VecDeque<usize>,
+) -> Result<(), Problem> {
+    match destination {
+        Destination::Bot(number) => {
+            let bot = bots.entry(*number).or_default();
+            let was_ready = bot.can_proceed();
+            if !bot.set_value(value) {
+                return Err(Problem::Overloaded {
+                    bot: *number,
+                    chips: 3,
+                });
+            }
+            if !was_ready && bot.can_proceed() {
+                ready.push_back(*number);
+            }
+        }
+        Destination::Output(number) => {
+            let output = outputs.entry(*number).or_default();
+            *output = value;
+            log.deliver(*number, value);
+        }
+    }
+    Ok(())
+}
+
 fn execute(
     instruction: &Instruction,
     bots: &mut HashMap<usize, Bot>,
     outputs: &mut HashMap<usize, usize>,
-) {
+    log: &mut Log,
+    ready: &mut VecDeque<usize>,
+) -> Result<(), Problem> {
     let (low_value, high_value) = {
         let bot = bots.entry(instruction.bot).or_default();
         if !bot.can_proceed() {
-            return;
+            return Ok(());
         }
 
         bot.get_low_high()
     };
+    log.compare(instruction.bot, low_value, high_value);
 
-    let low_destination_bot = &instruction.low.0;
-    let high_destination_bot = &instruction.high.0;
+    give(&instruction.low.0, low_value, bots, outputs, log, ready)?;
+    give(&instruction.high.0, high_value, bots, outputs, log, ready)
+}
 
-    match low_destination_bot {
-        Destination::Bot(number) => {
-            let bot = bots.entry(*number).or_default();
-            bot.set_value(low_value);
-        }
-        Destination::Output(number) => {
-            let output = outputs.entry(*number).or_default();
-            *output = low_value;
+// a network that passed validation never gives a bot a third chip, any other one stops there
+fn simulate(network: &Network) -> Result<(HashMap<usize, usize>, Log), Problem> {
+    let mut bots: HashMap<usize, Bot> = HashMap::new();
+    let mut outputs: HashMap<usize, usize> = HashMap::new();
+    let mut log = Log::new();
+
+    for (bot, value) in &network.values {
+        if !bots.entry(*bot).or_default().set_value(*value) {
+            return Err(Problem::Overloaded {
+                bot: *bot,
+                chips: 3,
+            });
         }
     }
+    let rules = network.rules();
+    let mut ready: Vec<usize> = bots
+        .iter()
+        .filter(|(_, bot)| bot.can_proceed())
+        .map(|(number, _)| *number)
+        .collect();
+    ready.sort_unstable();
+    let mut ready = VecDeque::from(ready);
 
-    match high_destination_bot {
-        Destination::Bot(number) => {
-            let bot = bots.entry(*number).or_default();
-            bot.set_value(high_value);
-        }
-        Destination::Output(number) => {
-            let output = outputs.entry(*number).or_default();
-            *output = high_value;
+    while let Some(number) = ready.pop_front() {
+        if let Some(instruction) = rules.get(&number) {
+            execute(instruction, &mut bots, &mut outputs, &mut log, &mut ready)?;
         }
     }
+
+    Ok((outputs, log))
 }
 
 fn main() {
     let input = fs::read_to_string("input").expect("file not found");
     let input = input.trim();
 
-    let mut bots: HashMap<usize, Bot> = HashMap::new();
-    let mut outputs: HashMap<usize, usize> = HashMap::new();
+    let network = network::parse(input).unwrap_or_else(|error| panic!("{}", error));
+    assert_eq!(Vec::<Problem>::new(), network.validate());
 
-    let set_value_regex = Regex::new(r"^value (?P<value>\d+) goes to bot (?P<bot>\d+)$").unwrap();
-    let pass_value_regex =
-        Regex::new(r"^bot (?P<bot>\d+) gives low to (?P<low_dest>\S+) (?P<low_number>\d+) and high to (?P<high_dest>\S+) (?P<high_number>\d+)$").unwrap();
-
-    let mut instructions = Vec::new();
-    for line in input.lines() {
-        if set_value_regex.is_match(line) {
-            let caps = set_value_regex.captures(line).unwrap();
-            let value = caps["value"].parse::<usize>().unwrap();
-            let bot = caps["bot"].parse::<usize>().unwrap();
-
-            let entry = bots.entry(bot).or_default();
-            entry.set_value(value);
-        } else if pass_value_regex.is_match(line) {
-            let caps = pass_value_regex.captures(line).unwrap();
-            let bot = caps["bot"].parse::<usize>().unwrap();
-            let low_number = caps["low_number"].parse::<usize>().unwrap();
-            let high_number = caps["high_number"].parse::<usize>().unwrap();
-
-            let low = match &caps["low_dest"] {
-                "bot" => Low(Destination::Bot(low_number)),
-                "output" => Low(Destination::Output(low_number)),
-                _ => panic!("unknown destination!"),
-            };
-
-            let high = match &caps["high_dest"] {
-                "bot" => High(Destination::Bot(high_number)),
-                "output" => High(Destination::Output(high_number)),
-                _ => panic!("unknown destination!"),
-            };
-
-            instructions.push(Instruction::new(bot, low, high));
-        }
-    }
-
-    let mut bot_comparing_17_61 = 0;
-    while bots.values().any(|bot| bot.can_proceed()) {
-        for instruction in instructions.iter() {
-            execute(instruction, &mut bots, &mut outputs);
-
-            for (number, bot) in bots.iter() {
-                if bot.microchip_1 == Some(17) && bot.microchip_2 == Some(61)
-                    || bot.microchip_2 == Some(17) && bot.microchip_1 == Some(61)
-                {
-                    bot_comparing_17_61 = *number;
-                }
-            }
-        }
-    }
+    let (outputs, log) = simulate(&network).unwrap_or_else(|problem| panic!("{}", problem));
 
     let output_0 = outputs.get(&0).unwrap();
     let output_1 = outputs.get(&1).unwrap();
     let output_2 = outputs.get(&2).unwrap();
 
-    assert_eq!(27, bot_comparing_17_61);
+    assert_eq!(Some(27), log.comparer(17, 61));
     assert_eq!(13_727, output_0 * output_1 * output_2);
+
+    assert_eq!(vec![17, 61], log.passed_through(27));
+    assert_eq!(vec![*output_0], log.delivered_to(0));
+    assert_eq!(231, log.events().len());
+
+    // `cargo run -- dot` writes the network, with the chips that went along every edge, to network.dot
+    if env::args().nth(1).as_deref() == Some("dot") {
+        network
+            .write_dot(
+                Some(&log),
+                &mut BufWriter::new(
+                    fs::File::create("network.dot").expect("failed to create file"),
+                ),
+            )
+            .expect("failed to write network");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "value 5 goes to bot 2
+bot 2 gives low to bot 1 and high to bot 0
+value 3 goes to bot 1
+bot 1 gives low to output 1 and high to bot 0
+bot 0 gives low to output 2 and high to output 0
+value 2 goes to bot 2";
+
+    fn run(input: &str) -> (HashMap<usize, usize>, Log) {
+        let network = network::parse(input).unwrap();
+        assert_eq!(Vec::<Problem>::new(), network.validate());
+        simulate(&network).unwrap()
+    }
+
+    // bot i passes its high chip on to bot i + 1, which also gets a chip of its own
+    fn chain(length: usize) -> String {
+        let mut input = String::from("value 0 goes to bot 0\nvalue 1 goes to bot 0\n");
+        for i in 0..length {
+            input.push_str(&format!(
+                "bot {} gives low to output {} and high to bot {}\n",
+                i,
+                i,
+                i + 1
+            ));
+            input.push_str(&format!("value {} goes to bot {}\n", i + 2, i + 1));
+        }
+        input.push_str(&format!(
+            "bot {} gives low to output {} and high to output {}\n",
+            length,
+            length,
+            length + 1
+        ));
+        input
+    }
+
+    #[test]
+    fn test_example() {
+        let (outputs, log) = run(EXAMPLE);
+        assert_eq!(Some(&5), outputs.get(&0));
+        assert_eq!(Some(&2), outputs.get(&1));
+        assert_eq!(Some(&3), outputs.get(&2));
+
+        assert_eq!(Some(2), log.comparer(5, 2));
+        assert_eq!(Some(1), log.comparer(2, 3));
+        assert_eq!(Some(0), log.comparer(3, 5));
+        assert_eq!(vec![3, 5], log.passed_through(0));
+        assert_eq!(vec![2], log.delivered_to(1));
+    }
+
+    #[test]
+    fn test_every_bot_fires_once() {
+        let length = 100_000;
+        let (outputs, log) = run(&chain(length));
+        assert_eq!(length + 2, outputs.len());
+        assert_eq!(2 * length + 3, log.events().len());
+        assert_eq!(Some(length), log.comparer(length, length + 1));
+        assert_eq!(vec![length, length + 1], log.passed_through(length));
+        assert_eq!(vec![length + 1], log.delivered_to(length + 1));
+    }
+
+    #[test]
+    fn test_overloaded() {
+        let overloaded = |input: &str| simulate(&network::parse(input).unwrap()).err();
+        assert_eq!(
+            Some(Problem::Overloaded { bot: 0, chips: 3 }),
+            overloaded("value 1 goes to bot 0\nvalue 2 goes to bot 0\nvalue 3 goes to bot 0")
+        );
+        assert_eq!(
+            Some(Problem::Overloaded { bot: 1, chips: 3 }),
+            overloaded(
+                "value 1 goes to bot 0
+value 2 goes to bot 0
+value 3 goes to bot 1
+bot 0 gives low to bot 1 and high to bot 1
+bot 1 gives low to output 0 and high to output 1"
+            )
+        );
+    }
+
+    // a chain of a million bots, run with `cargo test --release -- --ignored`
+    #[test]
+    #[ignore]
+    fn test_stress() {
+        let length = 1_000_000;
+        let (outputs, log) = run(&chain(length));
+        assert_eq!(length + 2, outputs.len());
+        assert_eq!(2 * length + 3, log.events().len());
+    }
 }
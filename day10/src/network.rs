@@ -0,0 +1,525 @@
+use crate::events::{Event, Log};
+use regex::Regex;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::io::{self, Write};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Destination {
+    Bot(usize),
+    Output(usize),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Low(pub Destination);
+#[derive(Debug, PartialEq)]
+pub struct High(pub Destination);
+
+#[derive(Debug, PartialEq)]
+pub struct Instruction {
+    pub bot: usize,
+    pub low: Low,
+    pub high: High,
+}
+
+impl Instruction {
+    fn new(bot: usize, low: Low, high: High) -> Self {
+        Instruction { bot, low, high }
+    }
+
+    fn destinations(&self) -> [Destination; 2] {
+        [self.low.0, self.high.0]
+    }
+}
+
+// line numbers start at 1
+#[derive(Debug, PartialEq)]
+pub enum ParseError {
+    Malformed { line: usize },
+    UnknownDestination { line: usize, name: String },
+    NumberOutOfRange { line: usize },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Malformed { line } => write!(f, "line {} is not an instruction", line),
+            ParseError::UnknownDestination { line, name } => {
+                write!(
+                    f,
+                    "line {} gives to an unknown destination '{}'",
+                    line, name
+                )
+            }
+            ParseError::NumberOutOfRange { line } => {
+                write!(f, "line {} has a number that is too large", line)
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Problem {
+    Overloaded { bot: usize, chips: usize },
+    MissingRule { bot: usize },
+    DuplicateRule { bot: usize, rules: usize },
+    UnreachableOutput { output: usize },
+    Cycle { bots: Vec<usize> },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Problem::Overloaded { bot, chips } => {
+                write!(f, "bot {} can be given {} chips but holds two", bot, chips)
+            }
+            Problem::MissingRule { bot } => write!(f, "bot {} is given chips but has no rule", bot),
+            Problem::DuplicateRule { bot, rules } => write!(f, "bot {} has {} rules", bot, rules),
+            Problem::UnreachableOutput { output } => {
+                write!(f, "output {} never receives a chip", output)
+            }
+            Problem::Cycle { bots } => {
+                let bots: Vec<String> = bots.iter().map(|bot| bot.to_string()).collect();
+                write!(f, "bots {} give chips in a cycle", bots.join(" -> "))
+            }
+        }
+    }
+}
+
+pub struct Network {
+    // (bot, value) for every chip handed out at the start
+    pub values: Vec<(usize, usize)>,
+    pub instructions: Vec<Instruction>,
+}
+
+fn number(text: &str, line: usize) -> Result<usize, ParseError> {
+    text.parse()
+        .map_err(|_| ParseError::NumberOutOfRange { line })
+}
+
+fn destination(kind: &str, number: usize, line: usize) -> Result<Destination, ParseError> {
+    match kind {
+        "bot" => Ok(Destination::Bot(number)),
+        "output" => Ok(Destination::Output(number)),
+        _ => Err(ParseError::UnknownDestination {
+            line,
+            name: kind.to_string(),
+        }),
+    }
+}
+
+pub fn parse(input: &str) -> Result<Network, ParseError> {
+    let set_value_regex = Regex::new(r"^value (?P<value>\d+) goes to bot (?P<bot>\d+)$").unwrap();
+    let pass_value_regex =
+        Regex::new(r"^bot (?P<bot>\d+) gives low to (?P<low_dest>\S+) (?P<low_number>\d+) and high to (?P<high_dest>\S+) (?P<high_number>\d+)$").unwrap();
+
+    let mut values = Vec::new();
+    let mut instructions = Vec::new();
+    for (i, line) in input.lines().enumerate() {
+        let line_number = i + 1;
+        if let Some(caps) = set_value_regex.captures(line) {
+            let value = number(&caps["value"], line_number)?;
+            let bot = number(&caps["bot"], line_number)?;
+            values.push((bot, value));
+        } else if let Some(caps) = pass_value_regex.captures(line) {
+            let bot = number(&caps["bot"], line_number)?;
+            let low_number = number(&caps["low_number"], line_number)?;
+            let high_number = number(&caps["high_number"], line_number)?;
+
+            let low = Low(destination(&caps["low_dest"], low_number, line_number)?);
+            let high = High(destination(&caps["high_dest"], high_number, line_number)?);
+
+            instructions.push(Instruction::new(bot, low, high));
+        } else {
+            return Err(ParseError::Malformed { line: line_number });
+        }
+    }
+
+    Ok(Network {
+        values,
+        instructions,
+    })
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Visit {
+    // position on the depth-first stack
+    Open(usize),
+    Done,
+}
+
+impl Network {
+    // the last rule for a bot wins, the validation reports the others
+    pub fn rules(&self) -> HashMap<usize, &Instruction> {
+        self.instructions
+            .iter()
+            .map(|instruction| (instruction.bot, instruction))
+            .collect()
+    }
+
+    pub fn validate(&self) -> Vec<Problem> {
+        let rules = self.rules();
+        let mut problems = Vec::new();
+
+        let mut counts: BTreeMap<usize, usize> = BTreeMap::new();
+        for instruction in &self.instructions {
+            *counts.entry(instruction.bot).or_default() += 1;
+        }
+        problems.extend(
+            counts
+                .into_iter()
+                .filter(|(_, rules)| *rules > 1)
+                .map(|(bot, rules)| Problem::DuplicateRule { bot, rules }),
+        );
+
+        // every chip a bot could be given, whether or not its givers ever fire
+        let mut incoming: BTreeMap<usize, usize> = BTreeMap::new();
+        for (bot, _) in &self.values {
+            *incoming.entry(*bot).or_default() += 1;
+        }
+        for instruction in rules.values() {
+            for destination in instruction.destinations().iter() {
+                if let Destination::Bot(bot) = destination {
+                    *incoming.entry(*bot).or_default() += 1;
+                }
+            }
+        }
+        for (bot, chips) in &incoming {
+            if *chips > 2 {
+                problems.push(Problem::Overloaded {
+                    bot: *bot,
+                    chips: *chips,
+                });
+            }
+            if !rules.contains_key(bot) {
+                problems.push(Problem::MissingRule { bot: *bot });
+            }
+        }
+
+        problems.extend(
+            self.unreachable_outputs(&rules)
+                .into_iter()
+                .map(|output| Problem::UnreachableOutput { output }),
+        );
+        problems.extend(
+            cycles(&rules)
+                .into_iter()
+                .map(|bots| Problem::Cycle { bots }),
+        );
+        problems
+    }
+
+    // counts chips through the network the way the simulation would, without their values
+    fn unreachable_outputs(&self, rules: &HashMap<usize, &Instruction>) -> BTreeSet<usize> {
+        let mut chips: HashMap<usize, usize> = HashMap::new();
+        let mut ready = Vec::new();
+        for (bot, _) in &self.values {
+            let count = chips.entry(*bot).or_default();
+            *count += 1;
+            if *count == 2 {
+                ready.push(*bot);
+            }
+        }
+
+        let mut outputs: BTreeSet<usize> = rules
+            .values()
+            .flat_map(|instruction| instruction.destinations().to_vec())
+            .filter_map(|destination| match destination {
+                Destination::Output(output) => Some(output),
+                Destination::Bot(_) => None,
+            })
+            .collect();
+        while let Some(bot) = ready.pop() {
+            let instruction = match rules.get(&bot) {
+                Some(instruction) => instruction,
+                None => continue,
+            };
+            for destination in instruction.destinations().iter() {
+                match destination {
+                    Destination::Bot(number) => {
+                        let count = chips.entry(*number).or_default();
+                        *count += 1;
+                        if *count == 2 {
+                            ready.push(*number);
+                        }
+                    }
+                    Destination::Output(number) => {
+                        outputs.remove(number);
+                    }
+                }
+            }
+        }
+        outputs
+    }
+
+    // with a log from a simulation, every edge also lists the chips that went along it
+    pub fn write_dot<W: Write>(&self, log: Option<&Log>, writer: &mut W) -> io::Result<()> {
+        let mut flowed: HashMap<(usize, &str), Vec<usize>> = HashMap::new();
+        for event in log.map_or(&[][..], |log| log.events()) {
+            if let Event::Comparison { bot, low, high, .. } = *event {
+                flowed.entry((bot, "low")).or_default().push(low);
+                flowed.entry((bot, "high")).or_default().push(high);
+            }
+        }
+
+        let mut rules: Vec<&Instruction> = self.rules().into_values().collect();
+        rules.sort_unstable_by_key(|instruction| instruction.bot);
+        let mut bots: BTreeSet<usize> = self.values.iter().map(|(bot, _)| *bot).collect();
+        let mut outputs = BTreeSet::new();
+        for instruction in &rules {
+            bots.insert(instruction.bot);
+            for destination in instruction.destinations().iter() {
+                match destination {
+                    Destination::Bot(bot) => bots.insert(*bot),
+                    Destination::Output(output) => outputs.insert(*output),
+                };
+            }
+        }
+
+        writeln!(writer, "digraph bots {{")?;
+        writeln!(writer, "    node [fontname=monospace];")?;
+        for bot in &bots {
+            writeln!(writer, "    b{} [label=\"bot {}\", shape=box];", bot, bot)?;
+        }
+        for output in &outputs {
+            writeln!(
+                writer,
+                "    o{} [label=\"output {}\", shape=ellipse];",
+                output, output
+            )?;
+        }
+        for (index, (bot, value)) in self.values.iter().enumerate() {
+            writeln!(
+                writer,
+                "    v{} [label=\"{}\", shape=plaintext];",
+                index, value
+            )?;
+            writeln!(writer, "    v{} -> b{};", index, bot)?;
+        }
+        for instruction in &rules {
+            let edges = [("low", instruction.low.0), ("high", instruction.high.0)];
+            for (kind, destination) in edges.iter() {
+                let target = match destination {
+                    Destination::Bot(bot) => format!("b{}", bot),
+                    Destination::Output(output) => format!("o{}", output),
+                };
+                let label = match flowed.get(&(instruction.bot, *kind)) {
+                    Some(values) => {
+                        let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+                        format!("{}: {}", kind, values.join(", "))
+                    }
+                    None => kind.to_string(),
+                };
+                writeln!(
+                    writer,
+                    "    b{} -> {} [label=\"{}\"];",
+                    instruction.bot, target, label
+                )?;
+            }
+        }
+        writeln!(writer, "}}")
+    }
+}
+
+// one cycle for every back edge an iterative depth-first search runs into
+fn cycles(rules: &HashMap<usize, &Instruction>) -> Vec<Vec<usize>> {
+    let mut bots: Vec<usize> = rules.keys().copied().collect();
+    bots.sort_unstable();
+
+    let mut visits: HashMap<usize, Visit> = HashMap::new();
+    let mut cycles = Vec::new();
+    for start in bots {
+        if visits.contains_key(&start) {
+            continue;
+        }
+        visits.insert(start, Visit::Open(0));
+        // (bot, index of the next destination to follow)
+        let mut stack = vec![(start, 0)];
+        while let Some((bot, edge)) = stack.last().copied() {
+            let destinations = match rules.get(&bot) {
+                Some(instruction) => instruction.destinations(),
+                None => [Destination::Output(0); 2],
+            };
+            if edge == destinations.len() {
+                visits.insert(bot, Visit::Done);
+                stack.pop();
+                continue;
+            }
+            stack.last_mut().unwrap().1 += 1;
+
+            let next = match destinations[edge] {
+                Destination::Bot(next) => next,
+                Destination::Output(_) => continue,
+            };
+            match visits.get(&next) {
+                None => {
+                    visits.insert(next, Visit::Open(stack.len()));
+                    stack.push((next, 0));
+                }
+                Some(Visit::Open(position)) => {
+                    cycles.push(stack[*position..].iter().map(|(bot, _)| *bot).collect());
+                }
+                Some(Visit::Done) => {}
+            }
+        }
+    }
+    cycles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problems(input: &str) -> Vec<Problem> {
+        parse(input).unwrap().validate()
+    }
+
+    #[test]
+    fn test_parse() {
+        let network =
+            parse("value 5 goes to bot 2\nbot 2 gives low to bot 1 and high to output 0").unwrap();
+        assert_eq!(vec![(2, 5)], network.values);
+        assert_eq!(
+            vec![Instruction::new(
+                2,
+                Low(Destination::Bot(1)),
+                High(Destination::Output(0))
+            )],
+            network.instructions
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            Some(ParseError::Malformed { line: 2 }),
+            parse("value 5 goes to bot 2\nvalue 3 goes to bot\n").err()
+        );
+        assert_eq!(
+            Some(ParseError::UnknownDestination {
+                line: 1,
+                name: String::from("robot")
+            }),
+            parse("bot 2 gives low to robot 1 and high to output 0").err()
+        );
+        assert_eq!(
+            Some(ParseError::NumberOutOfRange { line: 1 }),
+            parse("value 99999999999999999999999 goes to bot 2").err()
+        );
+        assert_eq!(
+            "line 4 is not an instruction",
+            ParseError::Malformed { line: 4 }.to_string()
+        );
+    }
+
+    #[test]
+    fn test_valid() {
+        assert_eq!(
+            Vec::<Problem>::new(),
+            problems(
+                "value 5 goes to bot 2
+bot 2 gives low to bot 1 and high to bot 0
+value 3 goes to bot 1
+bot 1 gives low to output 1 and high to bot 0
+bot 0 gives low to output 2 and high to output 0
+value 2 goes to bot 2"
+            )
+        );
+    }
+
+    #[test]
+    fn test_problems() {
+        assert_eq!(
+            vec![Problem::Overloaded { bot: 1, chips: 3 }],
+            problems(
+                "value 1 goes to bot 0
+value 2 goes to bot 0
+value 3 goes to bot 1
+bot 0 gives low to bot 1 and high to bot 1
+bot 1 gives low to output 0 and high to output 1"
+            )
+        );
+        assert_eq!(
+            vec![
+                Problem::MissingRule { bot: 1 },
+                Problem::UnreachableOutput { output: 1 },
+                Problem::UnreachableOutput { output: 2 }
+            ],
+            problems(
+                "value 1 goes to bot 0
+value 2 goes to bot 0
+bot 0 gives low to output 0 and high to bot 1
+bot 2 gives low to output 1 and high to output 2
+value 3 goes to bot 2"
+            )
+        );
+        assert_eq!(
+            vec![Problem::DuplicateRule { bot: 0, rules: 2 }],
+            problems(
+                "value 1 goes to bot 0
+value 2 goes to bot 0
+bot 0 gives low to output 0 and high to output 1
+bot 0 gives low to output 0 and high to output 1"
+            )
+        );
+    }
+
+    #[test]
+    fn test_cycles() {
+        assert_eq!(
+            vec![
+                Problem::UnreachableOutput { output: 0 },
+                Problem::UnreachableOutput { output: 1 },
+                Problem::Cycle {
+                    bots: vec![0, 1, 2]
+                },
+                Problem::Cycle { bots: vec![3] }
+            ],
+            problems(
+                "value 1 goes to bot 0
+bot 0 gives low to bot 1 and high to output 0
+bot 1 gives low to bot 2 and high to bot 2
+bot 2 gives low to bot 0 and high to output 1
+value 2 goes to bot 3
+bot 3 gives low to bot 3 and high to output 1"
+            )
+        );
+        assert_eq!(
+            "bots 0 -> 1 -> 2 give chips in a cycle",
+            Problem::Cycle {
+                bots: vec![0, 1, 2]
+            }
+            .to_string()
+        );
+    }
+
+    #[test]
+    fn test_write_dot() {
+        let network = parse(
+            "value 5 goes to bot 2
+bot 2 gives low to bot 1 and high to output 0
+value 3 goes to bot 1
+bot 1 gives low to output 1 and high to output 0",
+        )
+        .unwrap();
+        let mut log = Log::new();
+        log.compare(2, 3, 5);
+
+        let mut dot = Vec::new();
+        network.write_dot(None, &mut dot).unwrap();
+        let plain = String::from_utf8(dot).unwrap();
+        assert!(plain.starts_with("digraph bots {\n"));
+        assert!(plain.contains("    b1 [label=\"bot 1\", shape=box];\n"));
+        assert!(plain.contains("    o0 [label=\"output 0\", shape=ellipse];\n"));
+        assert!(plain.contains("    v1 [label=\"3\", shape=plaintext];\n    v1 -> b1;\n"));
+        assert!(plain.contains("    b2 -> b1 [label=\"low\"];\n"));
+
+        let mut dot = Vec::new();
+        network.write_dot(Some(&log), &mut dot).unwrap();
+        let simulated = String::from_utf8(dot).unwrap();
+        assert!(simulated.contains("    b2 -> b1 [label=\"low: 3\"];\n"));
+        assert!(simulated.contains("    b2 -> o0 [label=\"high: 5\"];\n"));
+        assert!(simulated.contains("    b1 -> o1 [label=\"low\"];\n"));
+        assert!(simulated.ends_with("}\n"));
+    }
+}